@@ -0,0 +1,147 @@
+//! Synchronous wrappers around the `async` API for native programs.
+//!
+//! This module is only available with the `blocking` feature enabled, and
+//! not on WASM targets.
+//!
+//! ```no_run
+//! use minidisc::blocking::BlockingContext;
+//! use minidisc::netmd::base::DEVICE_IDS_CROSSUSB;
+//!
+//! let mut context = BlockingContext::from_filters(DEVICE_IDS_CROSSUSB.to_vec())
+//!     .expect("Could not create context");
+//!
+//! let disc = context.list_content().expect("Could not list disc contents");
+//! ```
+use std::error::Error;
+
+use cross_usb::{Descriptor, DeviceFilter};
+use tokio::runtime::{Builder, Runtime};
+
+use crate::netmd::commands::{DeviceStatus, Disc, NetMDContext};
+use crate::netmd::interface::{DiscFormat, InterfaceError, MDTrack};
+
+/// A blocking wrapper around a [`NetMDContext`]
+///
+/// Each method runs the equivalent `async` method to completion on an
+/// internal runtime.
+pub struct BlockingContext {
+    context: NetMDContext,
+    runtime: Runtime,
+}
+
+impl BlockingContext {
+    /// Create a new blocking context to control a NetMD device
+    pub fn new(device: Descriptor) -> Result<Self, Box<dyn Error>> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let context = runtime.block_on(NetMDContext::new(device))?;
+
+        Ok(Self { context, runtime })
+    }
+
+    /// Find the first device matching any of the filters and open a blocking
+    /// context with it
+    pub fn from_filters(filters: Vec<DeviceFilter>) -> Result<Self, Box<dyn Error>> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let device = runtime.block_on(cross_usb::get_device(filters))?;
+        let context = runtime.block_on(NetMDContext::new(device))?;
+
+        Ok(Self { context, runtime })
+    }
+
+    /// Get a representation of the current disc inserted in the device.
+    pub fn list_content(&mut self) -> Result<Disc, InterfaceError> {
+        self.runtime.block_on(self.context.list_content())
+    }
+
+    /// Start downloading an [`MDTrack`] to the device, optionally verifying
+    /// the length of the new track.
+    #[allow(clippy::type_complexity)]
+    pub fn download<F>(
+        &mut self,
+        track: MDTrack,
+        progress_callback: F,
+        verify: bool,
+        disc_format: Option<DiscFormat>,
+    ) -> Result<(u16, Vec<u8>, Vec<u8>), InterfaceError>
+    where
+        F: Fn(usize, usize),
+    {
+        self.runtime.block_on(
+            self.context
+                .download(track, progress_callback, verify, disc_format),
+        )
+    }
+
+    /// Rename a disc while preserving group titles
+    pub fn rename_disc(
+        &mut self,
+        new_name: &str,
+        new_fw_name: Option<&str>,
+    ) -> Result<(), InterfaceError> {
+        self.runtime
+            .block_on(self.context.rename_disc(new_name, new_fw_name))
+    }
+
+    /// Get the current status of the device
+    pub fn device_status(&mut self) -> Result<DeviceStatus, InterfaceError> {
+        self.runtime.block_on(self.context.device_status())
+    }
+
+    /// Begin playback or resume after paused
+    pub fn play(&mut self) -> Result<(), InterfaceError> {
+        self.runtime.block_on(self.context.interface_mut().play())
+    }
+
+    /// Pause playback
+    pub fn pause(&mut self) -> Result<(), InterfaceError> {
+        self.runtime.block_on(self.context.interface_mut().pause())
+    }
+
+    /// Stop playback
+    pub fn stop(&mut self) -> Result<(), InterfaceError> {
+        self.runtime.block_on(self.context.interface_mut().stop())
+    }
+
+    /// Fast foward through the disc
+    pub fn fast_forward(&mut self) -> Result<(), InterfaceError> {
+        self.runtime
+            .block_on(self.context.interface_mut().fast_forward())
+    }
+
+    /// Rewind through the disc
+    pub fn rewind(&mut self) -> Result<(), InterfaceError> {
+        self.runtime.block_on(self.context.interface_mut().rewind())
+    }
+
+    /// Change to the next track (skip forward)
+    pub fn next_track(&mut self) -> Result<(), InterfaceError> {
+        self.runtime.block_on(self.context.next_track())
+    }
+
+    /// Change to the previous track (skip back)
+    pub fn previous_track(&mut self) -> Result<(), InterfaceError> {
+        self.runtime.block_on(self.context.previous_track())
+    }
+
+    /// Change to the beginning of the current track
+    pub fn restart_track(&mut self) -> Result<(), InterfaceError> {
+        self.runtime.block_on(self.context.restart_track())
+    }
+
+    /// Get a reference to the underlying async context.
+    pub fn context(&self) -> &NetMDContext {
+        &self.context
+    }
+
+    /// Get a mutable reference to the underlying async context.
+    ///
+    /// Any async method on it can be run with [`BlockingContext::block_on`].
+    pub fn context_mut(&mut self) -> &mut NetMDContext {
+        &mut self.context
+    }
+
+    /// Run a future to completion on the internal runtime
+    pub fn block_on<F: std::future::Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+}
@@ -3,6 +3,8 @@
 //! This crate is entirely `async` (a necessity because of USB in WASM), but
 //! it can be used in programs which are not async by using a crate like
 //! [futures_lite](https://docs.rs/futures-lite/) with the `block_on` function.
+//! On native targets, the `blocking` feature provides a synchronous
+//! `BlockingContext` which does this for you.
 //!
 //! To use this library, first you need to get a device from [`cross_usb`] and
 //! then open a [`NetMDContext`].
@@ -29,5 +31,8 @@
 
 pub mod netmd;
 
+#[cfg(all(feature = "blocking", not(target_family = "wasm")))]
+pub mod blocking;
+
 #[doc(inline)]
 pub use netmd::commands::NetMDContext;
@@ -7,7 +7,7 @@ use thiserror::Error;
 // USB stuff
 use cross_usb::prelude::*;
 use cross_usb::usb::{ControlIn, ControlOut, ControlType, Recipient, UsbError};
-use cross_usb::{Descriptor, Interface};
+use cross_usb::{Descriptor, Device, Interface};
 
 use super::utils::cross_sleep;
 
@@ -66,6 +66,33 @@ pub static DEVICE_IDS: &[DeviceId] = &[
 ];
 }
 
+/// Whether every entry of `ids` is named and has a unique vendor and
+/// product ID, checked when compiling below
+const fn device_ids_valid(ids: &[DeviceId]) -> bool {
+    let mut i = 0;
+    while i < ids.len() {
+        if ids[i].name.is_none() {
+            return false;
+        }
+
+        let mut j = i + 1;
+        while j < ids.len() {
+            if ids[i].vendor_id == ids[j].vendor_id && ids[i].product_id == ids[j].product_id {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+
+    true
+}
+
+const _: () = assert!(
+    device_ids_valid(DEVICE_IDS),
+    "DEVICE_IDS has an unnamed or duplicate entry"
+);
+
 /// Device IDs for use with [cross_usb]
 pub static DEVICE_IDS_CROSSUSB: Lazy<Box<[cross_usb::DeviceFilter]>> = Lazy::new(|| {
     DEVICE_IDS
@@ -79,6 +106,29 @@ pub static DEVICE_IDS_CROSSUSB: Lazy<Box<[cross_usb::DeviceFilter]>> = Lazy::new
         .collect()
 });
 
+/// Find a device in [`DEVICE_IDS`] by its name.
+///
+/// The match is case insensitive and on substrings, as some entries cover
+/// several models, such as `"Sony MZ-N710/NF810"`.
+pub fn device_id_by_name(name: &str) -> Option<&'static DeviceId> {
+    let name = name.to_lowercase();
+
+    DEVICE_IDS.iter().find(|d| {
+        d.name
+            .is_some_and(|device_name| device_name.to_lowercase().contains(&name))
+    })
+}
+
+/// Create a [cross_usb] filter for a device by its name, see [`device_id_by_name`]
+pub fn device_filter_by_name(name: &str) -> Option<cross_usb::DeviceFilter> {
+    device_id_by_name(name).map(|d| {
+        cross_usb::device_filter! {
+            vendor_id: d.vendor_id,
+            product_id: d.product_id,
+        }
+    })
+}
+
 /// The current status of the Minidisc device
 pub enum Status {
     Ready,
@@ -92,7 +142,7 @@ pub enum Status {
 }
 
 /// The ID of a device, including the name
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct DeviceId {
     vendor_id: u16,
     product_id: u16,
@@ -113,18 +163,51 @@ pub enum NetMDError {
     #[error("could not find device")]
     UnknownDevice(DeviceId),
 
+    #[error("the device was disconnected")]
+    Disconnected,
+
     #[error("usb connection error")]
-    UsbError(#[from] UsbError),
+    UsbError(UsbError),
+
+    #[error("could not write the data read from the device: {0}")]
+    WriteFailed(std::io::ErrorKind),
+
+    #[error("no NetMD interface found, interfaces which could be opened: {0:?}")]
+    NoInterface(Vec<u8>),
+
+    #[error("the given interface did not respond to any NetMD interface number")]
+    NotNetMDInterface,
+}
+
+impl From<UsbError> for NetMDError {
+    /// Errors meaning the device is gone are turned into
+    /// [`NetMDError::Disconnected`], so callers can tell them apart from
+    /// transient errors and reconnect.
+    fn from(error: UsbError) -> Self {
+        match error {
+            UsbError::Disconnected | UsbError::DeviceNotFound | UsbError::Invalid => {
+                NetMDError::Disconnected
+            }
+            error => NetMDError::UsbError(error),
+        }
+    }
 }
 
 /// A USB connection to a NetMD device
 pub struct NetMD {
     usb_interface: Interface,
+    interface_number: u8,
     model: DeviceId,
+    /// The product string the device reports over USB
+    product_string: Option<String>,
+    device_delay: Duration,
+    bulk_read_chunk_size: usize,
 }
 
 impl NetMD {
     const READ_REPLY_RETRY_INTERVAL: u32 = 10;
+    const MAX_INTERFACE_NUMBER: u8 = 4;
+    const DEFAULT_BULK_READ_CHUNK_SIZE: usize = 0x10000;
 
     /// Creates a new interface to a NetMD device
     pub async fn new(usb_descriptor: Descriptor) -> Result<Self, NetMDError> {
@@ -143,18 +226,95 @@ impl NetMD {
             }
         }
 
-        match model.name {
-            None => return Err(NetMDError::UnknownDevice(model)),
-            Some(_) => (),
+        if model.name.is_none() {
+            return Err(NetMDError::UnknownDevice(model));
         }
 
+        let product_string = usb_descriptor.product_string().await;
+
         let usb_device = usb_descriptor.open().await?;
-        let usb_interface = usb_device.open_interface(0).await?;
+        let (usb_interface, interface_number) = Self::open_netmd_interface(&usb_device).await?;
+
+        let mut netmd = Self::from_parts(usb_interface, interface_number, model);
+        netmd.product_string = product_string;
+
+        Ok(netmd)
+    }
+
+    /// Creates a new interface to a NetMD device from an interface which
+    /// has already been opened, for applications which manage the USB
+    /// device themselves.
+    ///
+    /// `model` should be the matching entry in [`DEVICE_IDS`]. The interface
+    /// number is found by polling the interface, so the interface must be
+    /// the one NetMD commands are sent to, otherwise
+    /// [`NetMDError::NotNetMDInterface`] is returned.
+    pub async fn from_interface(
+        usb_interface: Interface,
+        model: DeviceId,
+    ) -> Result<Self, NetMDError> {
+        for number in 0..Self::MAX_INTERFACE_NUMBER {
+            if Self::poll_interface(&usb_interface, number).await {
+                return Ok(Self::from_parts(usb_interface, number, model));
+            }
+        }
+
+        Err(NetMDError::NotNetMDInterface)
+    }
+
+    fn from_parts(usb_interface: Interface, interface_number: u8, model: DeviceId) -> Self {
+        // Sharp devices need some time to settle after transfer commands
+        let device_delay = match model.vendor_id {
+            0x04dd => Duration::from_millis(200),
+            _ => Duration::ZERO,
+        };
 
-        Ok(Self {
+        Self {
             usb_interface,
+            interface_number,
             model,
-        })
+            product_string: None,
+            device_delay,
+            bulk_read_chunk_size: Self::DEFAULT_BULK_READ_CHUNK_SIZE,
+        }
+    }
+
+    /// Whether `interface` responds to a poll as interface `number`
+    async fn poll_interface(interface: &Interface, number: u8) -> bool {
+        let poll = interface
+            .control_in(ControlIn {
+                control_type: ControlType::Vendor,
+                recipient: Recipient::Interface,
+                request: 0x01,
+                value: 0,
+                index: number as u16,
+                length: 4,
+            })
+            .await;
+
+        poll.is_ok_and(|result| result.len() == 4)
+    }
+
+    /// Find and open the interface of a device which NetMD commands are sent to.
+    ///
+    /// This is interface 0 on almost every device, but some composite
+    /// devices expose it under another number. Each interface is opened in
+    /// turn, and the first one which responds to a poll is used.
+    async fn open_netmd_interface(usb_device: &Device) -> Result<(Interface, u8), NetMDError> {
+        let mut opened = Vec::new();
+
+        for number in 0..Self::MAX_INTERFACE_NUMBER {
+            let Ok(interface) = usb_device.open_interface(number).await else {
+                continue;
+            };
+            opened.push(number);
+
+            if Self::poll_interface(&interface, number).await {
+                return Ok((interface, number));
+            }
+        }
+
+        Err(NetMDError::NoInterface(opened))
     }
 
     /// Gets the device name, this is limited to the devices in the list
@@ -162,6 +322,15 @@ impl NetMD {
         self.model.name
     }
 
+    /// Gets the product string the device reported over USB when it was
+    /// opened, which can be more specific than [`NetMD::device_name`].
+    ///
+    /// This is `None` when the device doesn't report one, or when the
+    /// connection was made with [`NetMD::from_interface`].
+    pub fn product_string(&self) -> Option<&str> {
+        self.product_string.as_deref()
+    }
+
     /// Gets the vendor id
     pub fn vendor_id(&self) -> u16 {
         self.model.vendor_id
@@ -172,6 +341,38 @@ impl NetMD {
         self.model.product_id
     }
 
+    /// Whether the device can upload tracks to the computer.
+    ///
+    /// This is only possible on the MZ-RH1.
+    pub fn supports_upload(&self) -> bool {
+        self.model.vendor_id == 0x054c && self.model.product_id == 0x0286
+    }
+
+    /// Gets the time to wait after commands which the device is slow to
+    /// process, such as the start of a transfer
+    pub fn device_delay(&self) -> Duration {
+        self.device_delay
+    }
+
+    /// Override the delay chosen for the device when it was opened
+    pub fn set_device_delay(&mut self, delay: Duration) {
+        self.device_delay = delay;
+    }
+
+    /// Gets the size of the chunks used when uploading tracks from the
+    /// device, which is how often the progress callback is called
+    pub fn bulk_read_chunk_size(&self) -> usize {
+        self.bulk_read_chunk_size
+    }
+
+    /// Set the size of the chunks used when uploading tracks from the device.
+    ///
+    /// Smaller chunks give finer progress updates at the cost of more USB
+    /// transfers. The default is 64KiB.
+    pub fn set_bulk_read_chunk_size(&mut self, chunk_size: usize) {
+        self.bulk_read_chunk_size = chunk_size.max(1);
+    }
+
     /// Poll the device to get either the result
     /// of the previous command, or the status
     pub async fn poll(&mut self) -> Result<(u16, [u8; 4]), NetMDError> {
@@ -183,7 +384,7 @@ impl NetMD {
                 recipient: Recipient::Interface,
                 request: 0x01,
                 value: 0,
-                index: 0,
+                index: self.interface_number as u16,
                 length: 4,
             })
             .await
@@ -237,7 +438,7 @@ impl NetMD {
                 recipient: Recipient::Interface,
                 request,
                 value: 0,
-                index: 0,
+                index: self.interface_number as u16,
                 data: &command,
             })
             .await
@@ -303,7 +504,7 @@ impl NetMD {
                 recipient: Recipient::Interface,
                 request,
                 value: 0,
-                index: 0,
+                index: self.interface_number as u16,
                 length,
             })
             .await?;
@@ -311,7 +512,12 @@ impl NetMD {
         Ok(reply)
     }
 
-    // Default chunksize should be 0x10000
+    /// Read `length` bytes of bulk data from the device in chunks of
+    /// `chunksize` bytes.
+    ///
+    /// The progress callback is called after each chunk is read with
+    /// `(length, done)`, where `done` is the total number of bytes read so
+    /// far. The last call always has `done == length`.
     pub async fn read_bulk<F: Fn(usize, usize)>(
         &mut self,
         length: usize,
@@ -332,29 +538,66 @@ impl NetMD {
         progress_callback: Option<F>,
     ) -> Result<Vec<u8>, NetMDError> {
         let mut final_result: Vec<u8> = Vec::new();
-        let mut done = 0;
 
-        while done < length {
-            let to_read = std::cmp::min(chunksize, length - done);
-            done -= to_read;
+        self.read_bulk_to_writer(length, chunksize, progress_callback, &mut final_result)
+            .await?;
+
+        Ok(final_result)
+    }
 
+    /// Read bulk data from the device, writing each chunk to `writer` as
+    /// soon as it is received instead of keeping it in memory.
+    ///
+    /// The progress callback is called the same way as in [`NetMD::read_bulk`].
+    pub async fn read_bulk_to_writer<F: Fn(usize, usize), W: std::io::Write>(
+        &mut self,
+        length: usize,
+        chunksize: usize,
+        progress_callback: Option<F>,
+        writer: &mut W,
+    ) -> Result<(), NetMDError> {
+        for (to_read, done) in bulk_read_chunks(length, chunksize) {
             let res = match self
                 .usb_interface
                 .bulk_in(BULK_READ_ENDPOINT, to_read)
                 .await
             {
                 Ok(result) => result,
-                Err(error) => return Err(NetMDError::UsbError(error)),
+                Err(error) => return Err(error.into()),
             };
 
             if let Some(cb) = &progress_callback {
                 cb(length, done)
             }
 
-            final_result.extend_from_slice(&res);
+            writer
+                .write_all(&res)
+                .map_err(|e| NetMDError::WriteFailed(e.kind()))?;
         }
 
-        Ok(final_result)
+        Ok(())
+    }
+
+    /// Clear a halt condition on the bulk endpoints.
+    ///
+    /// After a cancelled or failed bulk transfer the endpoints can be left
+    /// stalled, causing every following bulk transfer to fail.
+    pub async fn reset_endpoints(&mut self) -> Result<(), NetMDError> {
+        for endpoint in [BULK_WRITE_ENDPOINT, BULK_READ_ENDPOINT] {
+            // CLEAR_FEATURE(ENDPOINT_HALT)
+            self.usb_interface
+                .control_out(ControlOut {
+                    control_type: ControlType::Standard,
+                    recipient: Recipient::Endpoint,
+                    request: 0x01,
+                    value: 0,
+                    index: endpoint as u16,
+                    data: &[],
+                })
+                .await?;
+        }
+
+        Ok(())
     }
 
     pub async fn write_bulk(&mut self, data: &[u8]) -> Result<usize, NetMDError> {
@@ -364,3 +607,36 @@ impl NetMD {
             .await?)
     }
 }
+
+/// Split a bulk read of `length` bytes into chunks of at most `chunksize`
+/// bytes, giving the size of each chunk and the total read once it is done
+fn bulk_read_chunks(length: usize, chunksize: usize) -> impl Iterator<Item = (usize, usize)> {
+    let chunksize = chunksize.max(1);
+
+    (0..length).step_by(chunksize).map(move |start| {
+        let to_read = std::cmp::min(chunksize, length - start);
+        (to_read, start + to_read)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bulk_read_chunks_end_at_length() {
+        let chunks: Vec<_> = bulk_read_chunks(0x25000, 0x10000).collect();
+        assert_eq!(
+            chunks,
+            [(0x10000, 0x10000), (0x10000, 0x20000), (0x5000, 0x25000)]
+        );
+
+        let chunks: Vec<_> = bulk_read_chunks(0x20000, 0x10000).collect();
+        assert_eq!(chunks, [(0x10000, 0x10000), (0x10000, 0x20000)]);
+
+        let chunks: Vec<_> = bulk_read_chunks(100, 0x10000).collect();
+        assert_eq!(chunks, [(100, 100)]);
+
+        assert_eq!(bulk_read_chunks(0, 0x10000).count(), 0);
+    }
+}
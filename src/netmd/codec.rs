@@ -0,0 +1,108 @@
+//! The byte values devices use for codecs, in one place so the encodings,
+//! disc formats and wire formats can't drift apart.
+//!
+//! | Encoding | Track byte | Disc format  | Codec bits |
+//! |----------|------------|--------------|------------|
+//! | `SP`     | `0x90`     | `SPStereo`   | `6`        |
+//! |          |            | `SPMono`     | `4`        |
+//! | `LP2`    | `0x92`     | `LP2`        | `2`        |
+//! | `LP4`    | `0x93`     | `LP4`        | `0`        |
+
+use super::interface::{DiscFormat, Encoding, InterfaceError, WireFormat};
+
+/// The bits of a codec byte from a track upload which hold the disc format
+const DISC_FORMAT_MASK: u8 = 0x06;
+
+/// Get the encoding of a track from the byte the device reports for it
+pub(crate) fn encoding_from_byte(byte: u8) -> Result<Encoding, InterfaceError> {
+    match byte {
+        0x90 => Ok(Encoding::SP),
+        0x92 => Ok(Encoding::LP2),
+        0x93 => Ok(Encoding::LP4),
+        _ => Err(InterfaceError::InvalidEncoding(byte)),
+    }
+}
+
+/// Get the byte a device reports for a track of an encoding
+pub(crate) fn byte_from_encoding(encoding: Encoding) -> u8 {
+    encoding as u8
+}
+
+/// Get the disc format from the codec byte of a track upload, ignoring
+/// any bits which aren't part of the disc format
+pub(crate) fn disc_format_from_codec(codec: u8) -> Result<DiscFormat, InterfaceError> {
+    match codec & DISC_FORMAT_MASK {
+        0 => Ok(DiscFormat::LP4),
+        2 => Ok(DiscFormat::LP2),
+        4 => Ok(DiscFormat::SPMono),
+        6 => Ok(DiscFormat::SPStereo),
+        e => Err(InterfaceError::InvalidDiscFormat(e)),
+    }
+}
+
+/// Get the disc format a track sent in a wire format is recorded as
+pub(crate) const fn disc_format_for_wire(format: &WireFormat) -> DiscFormat {
+    match format {
+        WireFormat::Pcm => DiscFormat::SPStereo,
+        WireFormat::L105kbps => DiscFormat::LP2,
+        WireFormat::LP2 => DiscFormat::LP2,
+        WireFormat::LP4 => DiscFormat::LP4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding_bytes() {
+        for byte in 0..=u8::MAX {
+            let expected = match byte {
+                0x90 => Some(Encoding::SP),
+                0x92 => Some(Encoding::LP2),
+                0x93 => Some(Encoding::LP4),
+                _ => None,
+            };
+
+            match encoding_from_byte(byte) {
+                Ok(encoding) => {
+                    assert_eq!(Some(encoding), expected);
+                    assert_eq!(byte_from_encoding(encoding), byte);
+                }
+                Err(InterfaceError::InvalidEncoding(e)) => {
+                    assert_eq!(expected, None);
+                    assert_eq!(e, byte);
+                }
+                Err(e) => panic!("unexpected error for {byte:#04x}: {e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn disc_formats_from_codec() {
+        // Only the disc format bits matter, so every byte maps to a format
+        for codec in 0..=u8::MAX {
+            let expected = match (codec >> 1) & 0b11 {
+                0 => DiscFormat::LP4,
+                1 => DiscFormat::LP2,
+                2 => DiscFormat::SPMono,
+                _ => DiscFormat::SPStereo,
+            };
+
+            assert_eq!(disc_format_from_codec(codec).unwrap(), expected);
+            assert_eq!(disc_format_from_codec(codec).unwrap() as u8, codec & 0x06);
+        }
+    }
+
+    #[test]
+    fn disc_formats_for_wire() {
+        for (wire, disc) in [
+            (WireFormat::Pcm, DiscFormat::SPStereo),
+            (WireFormat::L105kbps, DiscFormat::LP2),
+            (WireFormat::LP2, DiscFormat::LP2),
+            (WireFormat::LP4, DiscFormat::LP4),
+        ] {
+            assert_eq!(disc_format_for_wire(&wire), disc);
+        }
+    }
+}
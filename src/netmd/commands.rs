@@ -1,17 +1,21 @@
 #![cfg_attr(debug_assertions, allow(dead_code))]
 use cross_usb::Descriptor;
-use num_derive::FromPrimitive;
-use num_traits::FromPrimitive;
 use regex::Regex;
-use std::error::Error;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::time::Duration;
 
+use crate::netmd::base::NetMD;
+use crate::netmd::factory_commands::get_descriptive_device_code;
 use crate::netmd::interface::DiscFlag;
-use crate::netmd::utils::{create_aea_header, create_wav_header, AeaOptions, RawTime};
+use crate::netmd::utils::{
+    aea_sound_groups, create_aea_header, create_wav_header, AeaOptions, RawTime,
+};
 
 use super::interface::{
-    Channels, Direction, DiscFormat, Encoding, InterfaceError, MDSession, MDTrack, NetMDInterface,
-    TrackFlag,
+    decode_title, parse_track_groups, Channels, Direction, DiscFormat, Encoding, InterfaceError,
+    MDSession, MDTrack, NetMDInterface, TrackFlag, TITLE_CELL_LIMIT,
 };
 use super::utils::{
     cross_sleep, half_width_title_length, half_width_to_full_width_range,
@@ -19,17 +23,53 @@ use super::utils::{
 };
 
 /// The current reported status from the device.
-#[derive(Debug, Clone, Copy, FromPrimitive, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OperatingStatus {
-    Ready = 50687,
-    Playing = 50037,
-    Paused = 50045,
-    FastForward = 49983,
-    Rewind = 49999,
-    ReadingTOC = 65315,
-    NoDisc = 65296,
-    DiscBlank = 65535,
-    ReadyForTransfer = 65319,
+    Ready,
+    Playing,
+    Paused,
+    FastForward,
+    Rewind,
+    ReadingTOC,
+    NoDisc,
+    DiscBlank,
+    ReadyForTransfer,
+    /// A status code which isn't recognized, kept for diagnostics
+    Unknown(u16),
+}
+
+impl OperatingStatus {
+    /// Get the status from the code the device reports
+    pub fn from_code(code: u16) -> Self {
+        match code {
+            50687 => Self::Ready,
+            50037 => Self::Playing,
+            50045 => Self::Paused,
+            49983 => Self::FastForward,
+            49999 => Self::Rewind,
+            65315 => Self::ReadingTOC,
+            65296 => Self::NoDisc,
+            65535 => Self::DiscBlank,
+            65319 => Self::ReadyForTransfer,
+            code => Self::Unknown(code),
+        }
+    }
+
+    /// Get the code the device reports for the status
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Ready => 50687,
+            Self::Playing => 50037,
+            Self::Paused => 50045,
+            Self::FastForward => 49983,
+            Self::Rewind => 49999,
+            Self::ReadingTOC => 65315,
+            Self::NoDisc => 65296,
+            Self::DiscBlank => 65535,
+            Self::ReadyForTransfer => 65319,
+            Self::Unknown(code) => *code,
+        }
+    }
 }
 
 /// A representation of time in the same way NetMD devices do.
@@ -44,6 +84,7 @@ pub struct Time {
 #[derive(Debug, Clone)]
 pub struct DeviceStatus {
     pub disc_present: bool,
+    /// The operating status, `None` if the device didn't report one
     pub state: Option<OperatingStatus>,
     pub track: u8,
     pub time: Time,
@@ -59,9 +100,38 @@ pub struct Track {
     channel: Channels,
     encoding: Encoding,
     protected: TrackFlag,
+    flags: u8,
 }
 
 impl Track {
+    /// Get the duration of the track
+    pub fn duration(&self) -> RawTime {
+        self.duration
+    }
+
+    /// Get the duration of the track as a [`Duration`]
+    pub fn duration_std(&self) -> Duration {
+        self.duration.as_duration()
+    }
+
+    /// Get the encoding of the track
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Get whether the track is protected from being edited or erased
+    pub fn protected(&self) -> TrackFlag {
+        self.protected
+    }
+
+    /// Get the raw flags byte of the track.
+    ///
+    /// Only the protection bits are understood, the other bits are
+    /// passed through here as the device reports them.
+    pub fn raw_flags(&self) -> u8 {
+        self.flags
+    }
+
     /// Get the number of title cells a title will take up.
     pub fn cells_for_title(&self) -> (usize, usize) {
         let encoding_name_correction = match self.encoding {
@@ -88,6 +158,39 @@ pub struct Group {
     tracks: Vec<Track>,
 }
 
+impl Group {
+    /// Get the index of the group on the disc
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Get the half width title of the group, or [`None`] for tracks
+    /// which are not in a group
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Get the full width title of the group
+    pub fn full_width_title(&self) -> Option<&str> {
+        self.full_width_title.as_deref()
+    }
+
+    /// Get the tracks in the group
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    /// The lowest and highest track index in the group
+    fn track_range(&self) -> (u16, u16) {
+        let indices = self.tracks.iter().map(|t| t.index);
+
+        (
+            indices.clone().min().unwrap_or_default(),
+            indices.max().unwrap_or_default(),
+        )
+    }
+}
+
 /// Information about a MiniDisc complete with [`Track`]s, [`Group`]s, and metadata.
 #[derive(Debug, Clone)]
 pub struct Disc {
@@ -100,6 +203,7 @@ pub struct Disc {
     total: u64,
     track_count: u16,
     groups: Vec<Group>,
+    invalid_raw_title: Option<Vec<u8>>,
 }
 
 impl Disc {
@@ -111,6 +215,33 @@ impl Disc {
             .unwrap() as u16
     }
 
+    /// Whether the disc is a recordable disc
+    pub fn is_writeable(&self) -> bool {
+        self.writeable
+    }
+
+    /// Whether the write protect tab of the disc is set
+    pub fn is_write_protected(&self) -> bool {
+        self.write_protected
+    }
+
+    /// The bytes of the raw disc title if they aren't valid Shift JIS.
+    ///
+    /// The disc title and group titles are then listed with the invalid
+    /// bytes replaced, and the groups of the disc can't be changed as that
+    /// would write the replaced title back.
+    pub fn invalid_raw_title(&self) -> Option<&[u8]> {
+        self.invalid_raw_title.as_deref()
+    }
+
+    /// Get the groups on the disc.
+    ///
+    /// Tracks which are not in a group are each given their own
+    /// [`Group`] without a title.
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+
     pub fn tracks(&self) -> Vec<Track> {
         let mut tracks: Vec<Track> = self.groups.iter().flat_map(|g| g.tracks.clone()).collect();
         tracks.sort_unstable_by_key(|t| t.index);
@@ -118,13 +249,264 @@ impl Disc {
         tracks
     }
 
+    /// Get a track by its index, without copying every track like
+    /// [`Disc::tracks`]
+    pub fn track(&self, index: u16) -> Option<&Track> {
+        self.groups
+            .iter()
+            .flat_map(|g| g.tracks.iter())
+            .find(|t| t.index == index)
+    }
+
+    /// Get the group a track is in
+    pub fn group_of(&self, track_index: u16) -> Option<&Group> {
+        self.groups
+            .iter()
+            .find(|g| g.tracks.iter().any(|t| t.index == track_index))
+    }
+
+    fn group_position(&self, index: u16) -> Result<usize, InterfaceError> {
+        self.groups
+            .iter()
+            .position(|g| g.index == index && g.title.is_some())
+            .ok_or_else(|| InterfaceError::GroupError(format!("group {} does not exist", index)))
+    }
+
+    fn renumber_groups(&mut self) {
+        for (i, group) in self.groups.iter_mut().enumerate() {
+            group.index = i as u16;
+        }
+    }
+
+    /// Merge two adjacent groups, keeping the title of group `a`.
+    fn merge_groups(&mut self, a: u16, b: u16) -> Result<(), InterfaceError> {
+        if a == b {
+            return Err(InterfaceError::GroupError(format!(
+                "cannot merge group {} with itself",
+                a
+            )));
+        }
+
+        let (min_a, max_a) = self.groups[self.group_position(a)?].track_range();
+        let (min_b, max_b) = self.groups[self.group_position(b)?].track_range();
+
+        // Groups on a MiniDisc can't be discontinuous
+        if max_a + 1 != min_b && max_b + 1 != min_a {
+            return Err(InterfaceError::GroupError(format!(
+                "groups {} and {} are not contiguous",
+                a, b
+            )));
+        }
+
+        let mut removed = self.groups.remove(self.group_position(b)?);
+        let position_a = self.group_position(a)?;
+        self.groups[position_a].tracks.append(&mut removed.tracks);
+        self.groups[position_a]
+            .tracks
+            .sort_unstable_by_key(|t| t.index);
+
+        self.renumber_groups();
+
+        Ok(())
+    }
+
+    /// Split a group in two, starting the new group at track index `at`.
+    fn split_group(&mut self, index: u16, at: u16) -> Result<(), InterfaceError> {
+        let position = self.group_position(index)?;
+        let (min, max) = self.groups[position].track_range();
+
+        if at <= min || at > max {
+            return Err(InterfaceError::GroupError(format!(
+                "track {} is not a boundary inside group {}",
+                at, index
+            )));
+        }
+
+        let group = &mut self.groups[position];
+        group.tracks.sort_unstable_by_key(|t| t.index);
+        let split_index = group.tracks.iter().position(|t| t.index >= at).unwrap();
+        let new_group = Group {
+            index: 0,
+            title: group.title.clone(),
+            full_width_title: group.full_width_title.clone(),
+            tracks: group.tracks.split_off(split_index),
+        };
+
+        self.groups.insert(position + 1, new_group);
+        self.renumber_groups();
+
+        Ok(())
+    }
+
+    /// Update the track indices and group membership after moving a track.
+    ///
+    /// Every other track stays in its group, while the moved track joins the
+    /// group of the track which previously occupied its new position.
+    fn move_track(&mut self, from: u16, to: u16) {
+        if from == to {
+            return;
+        }
+
+        let target_group = self
+            .groups
+            .iter()
+            .position(|g| g.tracks.iter().any(|t| t.index == to))
+            .filter(|p| self.groups[*p].title.is_some());
+
+        let Some(source_group) = self
+            .groups
+            .iter()
+            .position(|g| g.tracks.iter().any(|t| t.index == from))
+        else {
+            return;
+        };
+        let track_position = self.groups[source_group]
+            .tracks
+            .iter()
+            .position(|t| t.index == from)
+            .unwrap();
+        let mut moved = self.groups[source_group].tracks.remove(track_position);
+
+        for track in self.groups.iter_mut().flat_map(|g| g.tracks.iter_mut()) {
+            if from < to && track.index > from && track.index <= to {
+                track.index -= 1;
+            } else if from > to && track.index >= to && track.index < from {
+                track.index += 1;
+            }
+        }
+        moved.index = to;
+
+        match target_group {
+            Some(position) => self.groups[position].tracks.push(moved),
+            None => self.groups.insert(
+                0,
+                Group {
+                    index: 0,
+                    title: None,
+                    full_width_title: None,
+                    tracks: vec![moved],
+                },
+            ),
+        }
+
+        self.groups.retain(|g| !g.tracks.is_empty());
+        for group in self.groups.iter_mut() {
+            group.tracks.sort_unstable_by_key(|t| t.index);
+        }
+        self.renumber_groups();
+    }
+
+    /// Update the track indices and group membership after erasing a track.
+    ///
+    /// Groups left without any tracks are removed.
+    fn erase_track(&mut self, index: u16) {
+        for group in self.groups.iter_mut() {
+            group.tracks.retain(|t| t.index != index);
+        }
+
+        for track in self.groups.iter_mut().flat_map(|g| g.tracks.iter_mut()) {
+            if track.index > index {
+                track.index -= 1;
+            }
+        }
+
+        self.groups.retain(|g| !g.tracks.is_empty());
+        self.track_count = self.track_count.saturating_sub(1);
+        self.renumber_groups();
+    }
+
+    fn track_mut(&mut self, index: u16) -> Option<&mut Track> {
+        self.groups
+            .iter_mut()
+            .flat_map(|g| g.tracks.iter_mut())
+            .find(|t| t.index == index)
+    }
+
+    /// Get the combined duration of every track on the disc
+    pub fn total_duration(&self) -> RawTime {
+        self.groups
+            .iter()
+            .flat_map(|g| g.tracks.iter())
+            .map(|t| t.duration())
+            .sum()
+    }
+
+    /// Get the recorded time on the disc in SP, as a [`Duration`]
+    pub fn used_duration(&self) -> Duration {
+        RawTime::from_frames(self.used).as_duration()
+    }
+
+    /// Get the total SP capacity of the disc, as a [`Duration`].
+    ///
+    /// This is the size of the disc, see [`Disc::total_duration`] for the
+    /// length of the tracks on it.
+    pub fn capacity_duration(&self) -> Duration {
+        RawTime::from_frames(self.total).as_duration()
+    }
+
+    /// Get the time left on the disc in SP, as a [`Duration`]
+    pub fn left_duration(&self) -> Duration {
+        RawTime::from_frames(self.left).as_duration()
+    }
+
+    /// Get the combined duration of the tracks on the disc for each [`Encoding`]
+    pub fn duration_by_encoding(&self) -> HashMap<Encoding, RawTime> {
+        let mut durations: HashMap<Encoding, RawTime> = HashMap::new();
+
+        for track in self.groups.iter().flat_map(|g| g.tracks.iter()) {
+            let total = durations
+                .entry(track.encoding())
+                .or_insert(RawTime::from_frames(0));
+            *total = *total + track.duration();
+        }
+
+        durations
+    }
+
+    /// Get the time left on the disc when recording with a specific [`Encoding`].
+    ///
+    /// The remaining space is stored as SP frames, LP2 and LP4 fit two and
+    /// four times as much audio in the same space.
+    pub fn time_left(&self, encoding: Encoding) -> RawTime {
+        let multiplier = match encoding {
+            Encoding::SP => 1,
+            Encoding::LP2 => 2,
+            Encoding::LP4 => 4,
+        };
+
+        RawTime::from_frames(self.left * multiplier)
+    }
+
+    /// Whether there is no room left to record, meaning less than a single
+    /// SP sound group of 512 samples is left
+    pub fn is_full(&self) -> bool {
+        // `left` is in 1/512ths of a second
+        self.left * 44100 < 512 * 512
+    }
+
+    /// Get the fraction of the disc which has been recorded on, from 0 to 1
+    pub fn fraction_used(&self) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+
+        self.used as f32 / self.total as f32
+    }
+
+    /// Get the highest quality [`Encoding`] which can fit a track of the
+    /// given duration in the remaining space, or [`None`] if nothing fits.
+    pub fn best_encoding_for(&self, duration: RawTime) -> Option<Encoding> {
+        [Encoding::SP, Encoding::LP2, Encoding::LP4]
+            .into_iter()
+            .filter(|e| self.time_left(*e).as_frames() >= duration.as_frames())
+            .min()
+    }
+
     fn remaining_characters_for_titles(
         &self,
         ignore_disc_titles: bool,
         include_groups: bool,
     ) -> (usize, usize) {
-        const CELL_LIMIT: usize = 255;
-
         let groups = self.groups.iter().filter(|g| g.title.is_some());
 
         let (disc_fw_title, disc_hw_title) = if !ignore_disc_titles {
@@ -168,12 +550,20 @@ impl Disc {
         }
 
         (
-            usize::max(CELL_LIMIT - used_full_width_cells, 0) * 7,
-            usize::max(CELL_LIMIT - used_half_width_cells, 0) * 7,
+            TITLE_CELL_LIMIT.saturating_sub(used_full_width_cells) * 7,
+            TITLE_CELL_LIMIT.saturating_sub(used_half_width_cells) * 7,
         )
     }
 
     pub fn compile_disc_titles(&self) -> (String, String) {
+        let (title, full_width_title, _) = self.compile_disc_titles_checked();
+        (title, full_width_title)
+    }
+
+    /// Compile the raw disc titles like [`Disc::compile_disc_titles`], also
+    /// returning whether any group title was dropped as it didn't fit
+    fn compile_disc_titles_checked(&self) -> (String, String, bool) {
+        let mut dropped = false;
         let (available_full_width, available_half_width) =
             self.remaining_characters_for_titles(true, false);
 
@@ -237,11 +627,15 @@ impl Disc {
                     chars_to_cells(new_raw_full_width_title_after_group.len() * 2) * 7;
                 if available_full_width as isize - full_width_titles_length_in_toc as isize >= 0 {
                     new_raw_full_width_title = new_raw_full_width_title_after_group
+                } else {
+                    dropped = true;
                 }
             }
 
             if available_half_width as isize - half_width_titles_length_in_toc as isize >= 0 {
                 new_raw_title = new_raw_title_after_group
+            } else {
+                dropped = true;
             }
         }
 
@@ -250,9 +644,11 @@ impl Disc {
         let full_width_titles_length_in_toc = chars_to_cells(new_raw_full_width_title.len() * 2);
 
         if (available_half_width as isize - half_width_titles_length_in_toc as isize) < 0 {
+            dropped |= !new_raw_title.is_empty();
             new_raw_title = String::new();
         }
         if (available_full_width as isize - full_width_titles_length_in_toc as isize) < 0 {
+            dropped |= use_full_width && !new_raw_full_width_title.is_empty();
             new_raw_full_width_title = String::new();
         }
 
@@ -263,8 +659,103 @@ impl Disc {
             } else {
                 String::new()
             },
+            dropped,
         )
     }
+
+    /// Find the changes needed to turn this disc into `other`.
+    ///
+    /// Tracks are compared by index, so only tracks present on both discs
+    /// are compared.
+    pub fn diff(&self, other: &Disc) -> DiscChanges {
+        // The disc title and groups are stored together in the disc title
+        let titles = self.compile_disc_titles();
+        let other_titles = other.compile_disc_titles();
+        let disc_titles = (titles != other_titles).then_some(other_titles);
+
+        let other_tracks = other.tracks();
+        let track_titles = self
+            .tracks()
+            .into_iter()
+            .filter_map(|track| {
+                let other_track = other_tracks.iter().find(|t| t.index == track.index)?;
+
+                let change = TrackTitleChange {
+                    index: track.index,
+                    title: (track.title != other_track.title).then(|| other_track.title.clone()),
+                    full_width_title: (track.full_width_title != other_track.full_width_title)
+                        .then(|| other_track.full_width_title.clone()),
+                };
+
+                (change.title.is_some() || change.full_width_title.is_some()).then_some(change)
+            })
+            .collect();
+
+        DiscChanges {
+            disc_titles,
+            track_titles,
+        }
+    }
+}
+
+/// The changes between two [`Disc`]s, created by [`Disc::diff`] and written
+/// to the device with [`NetMDContext::apply_changes`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiscChanges {
+    /// The new raw half width and full width disc titles, which contain
+    /// both the disc title and the groups
+    pub disc_titles: Option<(String, String)>,
+    /// The tracks whose titles have changed
+    pub track_titles: Vec<TrackTitleChange>,
+}
+
+impl DiscChanges {
+    /// Whether there are no changes
+    pub fn is_empty(&self) -> bool {
+        self.disc_titles.is_none() && self.track_titles.is_empty()
+    }
+}
+
+/// A change to the titles of a single track, see [`DiscChanges`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackTitleChange {
+    pub index: u16,
+    /// The new half width title, if it changed
+    pub title: Option<String>,
+    /// The new full width title, if it changed
+    pub full_width_title: Option<String>,
+}
+
+/// The raw state of a disc and device, for bug reports.
+///
+/// Titles are the bytes stored on the disc, before they are decoded from
+/// Shift JIS and before the groups are parsed out, so titles which can't be
+/// decoded are still reported. With the `serde` feature enabled this can be
+/// serialized.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DiagnosticReport {
+    pub device_name: Option<String>,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    /// The device code, if it was requested and the device has a factory
+    /// interface
+    pub device_code: Option<String>,
+    pub disc_flags: u8,
+    pub track_count: u16,
+    pub raw_disc_title: Vec<u8>,
+    pub raw_full_width_disc_title: Vec<u8>,
+    pub tracks: Vec<TrackDiagnostics>,
+}
+
+/// The raw state of a single track in a [`DiagnosticReport`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TrackDiagnostics {
+    pub index: u16,
+    pub title: Vec<u8>,
+    pub full_width_title: Vec<u8>,
+    pub flags: u8,
 }
 
 /// Context for interacting with a NetMD device as a wrapper around a [`NetMDInterface`].
@@ -272,16 +763,55 @@ impl Disc {
 /// This struct wraps a [`NetMDInterface`] and allows for some higher level
 /// functions, but it is still necessary to interact with the [`NetMDInterface`]
 /// when performing many operations.
+///
+/// Edits to titles, groups and tracks are not persistent until the disc is
+/// ejected or [`NetMDContext::commit_changes`] is called.
 pub struct NetMDContext {
     interface: NetMDInterface,
+    disc: Option<Disc>,
+    /// The track count and raw disc title hash of the last listing
+    listing_fingerprint: Option<(u16, u64)>,
 }
 
 impl NetMDContext {
+    const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(200);
+    /// Reading the TOC of a freshly inserted disc can take a while
+    const DOWNLOAD_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
     /// Create a new context to control a NetMD device
     pub async fn new(device: Descriptor) -> Result<Self, InterfaceError> {
         let interface = NetMDInterface::new(device).await?;
 
-        Ok(Self { interface })
+        Ok(Self {
+            interface,
+            disc: None,
+            listing_fingerprint: None,
+        })
+    }
+
+    /// Re-open the connection to the device in place, such as after it
+    /// dropped off the bus while asleep.
+    ///
+    /// The cached disc listing and the connection settings are kept, unless
+    /// `device` is a different model to the one previously connected.
+    pub async fn reconnect(&mut self, device: Descriptor) -> Result<(), InterfaceError> {
+        let mut netmd = NetMD::new(device).await?;
+        let old = &self.interface.device;
+
+        if netmd.vendor_id() == old.vendor_id() && netmd.product_id() == old.product_id() {
+            netmd.set_device_delay(old.device_delay());
+            netmd.set_bulk_read_chunk_size(old.bulk_read_chunk_size());
+        } else {
+            self.disc = None;
+            self.listing_fingerprint = None;
+        }
+
+        let mut interface = NetMDInterface::from_netmd(netmd);
+        interface.set_max_interim_read_attempts(self.interface.max_interim_read_attempts());
+        interface.set_interim_retry_interval(self.interface.interim_retry_interval());
+        self.interface = interface;
+
+        Ok(())
     }
 
     /// Change to the next track (skip forward)
@@ -300,17 +830,15 @@ impl NetMDContext {
     }
 
     /// Get the current status of the device
-    pub async fn device_status(&mut self) -> Result<DeviceStatus, Box<dyn Error>> {
+    pub async fn device_status(&mut self) -> Result<DeviceStatus, InterfaceError> {
         let status = self.interface.status().await?;
-        let playback_status = self.interface.playback_status2().await?;
-        let b1: u16 = playback_status[4] as u16;
-        let b2: u16 = playback_status[5] as u16;
+        let playback_status = self.interface.playback_status2_parsed().await?;
         let position = self.interface.position().await?;
-        let operating_status = b1 << 8 | b2;
 
         let track = position[0] as u8;
         let disc_present = status[4] != 0x80;
-        let mut state: Option<OperatingStatus> = FromPrimitive::from_u16(operating_status);
+        let mut state: Option<OperatingStatus> =
+            playback_status.state().map(OperatingStatus::from_code);
 
         if state == Some(OperatingStatus::Playing) && !disc_present {
             state = Some(OperatingStatus::Ready);
@@ -331,12 +859,33 @@ impl NetMDContext {
     }
 
     /// Get a representation of the current disc inserted in the device.
-    pub async fn list_content(&mut self) -> Result<Disc, Box<dyn Error>> {
+    pub async fn list_content(&mut self) -> Result<Disc, InterfaceError> {
+        self.list_content_inner(false).await
+    }
+
+    async fn list_content_inner(&mut self, repair_groups: bool) -> Result<Disc, InterfaceError> {
         let flags = self.interface.disc_flags().await?;
-        let title = self.interface.disc_title(false).await?;
-        let full_width_title = self.interface.disc_title(true).await?;
+
+        // The raw titles are only read once, so the title, the groups and
+        // the fingerprint all come from the same read
+        let raw_title_bytes = self.interface.raw_disc_title_bytes(false).await?;
+        let raw_full_width_title_bytes = self.interface.raw_disc_title_bytes(true).await?;
+        let (raw_title, title_invalid) = decode_title(&raw_title_bytes);
+        let (raw_full_width_title, full_width_title_invalid) =
+            decode_title(&raw_full_width_title_bytes);
+
+        let invalid_raw_title = match (title_invalid, full_width_title_invalid) {
+            (true, _) => Some(raw_title_bytes.clone()),
+            (false, true) => Some(raw_full_width_title_bytes),
+            (false, false) => None,
+        };
+        let title = NetMDInterface::disc_title_from_raw(raw_title.clone(), false);
+        let full_width_title =
+            NetMDInterface::disc_title_from_raw(raw_full_width_title.clone(), true);
+
         let disc_capacity: [RawTime; 3] = self.interface.disc_capacity().await?;
         let track_count = self.interface.track_count().await?;
+        let fingerprint = disc_fingerprint(track_count, &raw_title_bytes);
 
         let mut frames_used = disc_capacity[0].as_frames();
         let mut frames_total = disc_capacity[1].as_frames();
@@ -349,26 +898,43 @@ impl NetMDContext {
             frames_left /= 2;
         }
 
-        let track_group_list = self.interface.track_group_list().await?;
+        let track_group_list = parse_track_groups(
+            &raw_title,
+            &raw_full_width_title,
+            track_count,
+            repair_groups,
+        )?;
+
+        // Each property is read for every track at once, so its descriptor
+        // is only opened and closed once. This takes 5 queries per track
+        // plus 10 in total, instead of 15 queries per track.
+        let all_tracks: Vec<u16> = (0..track_count).collect();
+        let encodings = self.interface.track_encodings(all_tracks.clone()).await?;
+        let durations = self.interface.track_lengths(all_tracks.clone()).await?;
+        let track_flags = self.interface.track_flags_list(all_tracks.clone()).await?;
+
+        let titles = self
+            .interface
+            .track_titles(all_tracks.clone(), false)
+            .await?;
+        let full_width_titles = self.interface.track_titles(all_tracks, true).await?;
 
         let mut groups = vec![];
         for (index, group) in track_group_list.iter().enumerate() {
             let mut tracks = vec![];
             for track in &group.2 {
-                let (encoding, channel) = self.interface.track_encoding(*track).await.unwrap();
-                let duration = self.interface.track_length(*track).await?;
-                let flags = self.interface.track_flags(*track).await?;
-                let title = self.interface.track_title(*track, false).await?;
-                let full_width_title = self.interface.track_title(*track, true).await?;
+                let i = *track as usize;
+                let (encoding, channel) = encodings[i];
 
                 tracks.push(Track {
                     index: *track,
-                    title,
-                    full_width_title,
-                    duration,
+                    title: titles[i].clone(),
+                    full_width_title: full_width_titles[i].clone(),
+                    duration: durations[i],
                     channel,
                     encoding,
-                    protected: TrackFlag::from_u8(flags).unwrap(),
+                    protected: TrackFlag::from_flags(track_flags[i]),
+                    flags: track_flags[i],
                 })
             }
 
@@ -390,178 +956,743 @@ impl NetMDContext {
             total: frames_total,
             track_count,
             groups,
+            invalid_raw_title,
         };
 
+        self.disc = Some(disc.clone());
+        self.listing_fingerprint = Some(fingerprint);
+
         Ok(disc)
     }
 
-    pub async fn rewrite_disc_groups(&mut self, disc: Disc) -> Result<(), Box<dyn Error>> {
-        let (new_raw_title, new_raw_full_width_title) = disc.compile_disc_titles();
+    /// Fix the groups of a disc which has overlapping or malformed group
+    /// ranges, such as one edited by buggy software, which
+    /// [`NetMDContext::list_content`] refuses to list.
+    ///
+    /// When a track is in more than one group it stays in the first one, and
+    /// groups with malformed ranges are removed. A group which is left with
+    /// a gap is split in two. Nothing is written if the groups are already
+    /// valid.
+    pub async fn repair_groups(&mut self) -> Result<(), InterfaceError> {
+        if self.interface.track_group_list().await.is_ok() {
+            return Ok(());
+        }
+
+        let disc = self.list_content_inner(true).await?;
+        self.rewrite_disc_groups(disc).await?;
 
-        self.interface.set_disc_title(&new_raw_title, false).await?;
-        self.interface
-            .set_disc_title(&new_raw_full_width_title, false)
-            .await?;
+        // The fingerprint was taken from the broken title
+        self.listing_fingerprint = None;
 
         Ok(())
     }
 
-    /// Rename a disc while preserving group titles
-    pub async fn rename_disc(
-        &mut self,
-        new_name: &str,
-        new_fw_name: Option<&str>,
-    ) -> Result<(), Box<dyn Error>> {
-        let new_name = sanitize_half_width_title(new_name);
-        let new_fw_name = new_fw_name.map(sanitize_full_width_title);
+    /// Forget the cached disc and list the content of the disc again, for
+    /// when the disc has been changed outside of this context
+    pub async fn reload(&mut self) -> Result<Disc, InterfaceError> {
+        self.disc = None;
+        self.listing_fingerprint = None;
 
-        let old_name = self.interface.disc_title(false).await?;
-        let old_fw_name = self.interface.disc_title(true).await?;
-        let old_raw_name = self.interface.raw_disc_title(false).await?;
-        let old_raw_fw_name = self.interface.raw_disc_title(true).await?;
-
-        let has_groups = old_raw_name.contains("//");
-        let has_fw_groups = old_raw_fw_name.contains("／／");
-
-        let has_groups_and_title = old_raw_name.starts_with("0;");
-        let has_fw_groups_and_title = old_raw_fw_name.starts_with("０；");
-
-        if new_fw_name.as_ref().is_some_and(|n| n != &old_fw_name) {
-            let new_fw_name_with_groups;
-            if has_fw_groups {
-                if has_fw_groups_and_title {
-                    let re = Regex::new(r"^０；.*?／／").unwrap();
-                    new_fw_name_with_groups = re
-                        .replace_all(
-                            &old_raw_fw_name,
-                            if !new_fw_name.as_ref().unwrap().is_empty() {
-                                format!("０；{}／／", new_fw_name.unwrap())
-                            } else {
-                                String::new()
-                            },
-                        )
-                        .into()
-                } else {
-                    new_fw_name_with_groups =
-                        format!("０；{}／／{}", new_fw_name.unwrap(), old_raw_fw_name);
-                }
-            } else {
-                new_fw_name_with_groups = new_fw_name.unwrap();
-            }
+        self.list_content().await
+    }
 
-            self.interface
-                .set_disc_title(&new_fw_name_with_groups, true)
-                .await?;
-        }
+    /// Check whether the disc differs from the last [`NetMDContext::list_content`],
+    /// for example because it was swapped or edited on the device itself.
+    ///
+    /// This only compares the track count and the raw disc title, which
+    /// holds the groups, so it is much cheaper than listing the disc again.
+    /// Edits made through this context also count as changes. Returns
+    /// `true` if the disc has not been listed yet.
+    pub async fn disc_changed(&mut self) -> Result<bool, InterfaceError> {
+        let Some(last_fingerprint) = self.listing_fingerprint else {
+            return Ok(true);
+        };
 
-        if new_name == old_name {
-            return Ok(());
-        }
+        let track_count = self.interface.track_count().await?;
+        let raw_title = self.interface.raw_disc_title_bytes(false).await?;
 
-        let new_name_with_groups;
-        if has_groups {
-            if has_groups_and_title {
-                let re = Regex::new(r"^0;.*?\/\/").unwrap();
-                new_name_with_groups = re
-                    .replace_all(
-                        &old_raw_name,
-                        if !new_name.is_empty() {
-                            format!("0;{}//", new_name)
-                        } else {
-                            String::new()
-                        },
-                    )
-                    .into()
-            } else {
-                new_name_with_groups = format!("0;{}//{}", new_name, old_raw_name);
-            }
-        } else {
-            new_name_with_groups = new_name
+        Ok(disc_fingerprint(track_count, &raw_title) != last_fingerprint)
+    }
+
+    pub async fn rewrite_disc_groups(&mut self, disc: Disc) -> Result<(), InterfaceError> {
+        if let Some(bytes) = disc.invalid_raw_title {
+            return Err(InterfaceError::InvalidTitleEncoding(bytes));
         }
 
-        self.interface
-            .set_disc_title(&new_name_with_groups, false)
+        self.write_raw_disc_titles(disc.compile_disc_titles())
             .await?;
 
+        self.disc = Some(disc);
+
         Ok(())
     }
 
-    /// Get a track from the device. This only works with MZ-RH1 devices.
-    pub async fn upload<F: Fn(usize, usize)>(
+    /// Read a raw disc title which is going to be written back with changes.
+    ///
+    /// A title which isn't valid Shift JIS is an error, as writing it back
+    /// would replace the invalid bytes for good.
+    async fn editable_raw_disc_title(&mut self, wchar: bool) -> Result<String, InterfaceError> {
+        let bytes = self.interface.raw_disc_title_bytes(wchar).await?;
+
+        match decode_title(&bytes) {
+            (title, false) => Ok(title),
+            (_, true) => Err(InterfaceError::InvalidTitleEncoding(bytes)),
+        }
+    }
+
+    /// Write the raw half width and full width disc titles
+    async fn write_raw_disc_titles(
         &mut self,
-        track: u16,
-        progress_callback: Option<F>,
-    ) -> Result<(DiscFormat, Vec<u8>), Box<dyn Error>> {
-        let mut output_vec = Vec::new();
-        let (format, _frames, result) = self
-            .interface
-            .save_track_to_array(track, progress_callback)
-            .await?;
+        (raw_title, raw_full_width_title): (String, String),
+    ) -> Result<(), InterfaceError> {
+        for (title, wchar) in [(raw_title, false), (raw_full_width_title, true)] {
+            self.interface.set_disc_title(&title, wchar).await?;
+        }
 
-        let header;
-        match format {
-            DiscFormat::SPMono | DiscFormat::SPStereo => {
-                let aea_options = AeaOptions {
-                    name: &self.interface.track_title(track, false).await?,
-                    channels: if format == DiscFormat::SPStereo { 2 } else { 1 },
-                    sound_groups: f32::floor(result.len() as f32 / 212.0) as u32,
-                    ..Default::default()
-                };
-                header = create_aea_header(aea_options);
+        Ok(())
+    }
+
+    /// Write only the fields in `changes` to the device.
+    ///
+    /// Compared to writing every title, this keeps the number of TOC writes
+    /// down and avoids overwriting titles changed elsewhere in the meantime.
+    pub async fn apply_changes(&mut self, changes: &DiscChanges) -> Result<(), InterfaceError> {
+        self.ensure_writable().await?;
+
+        if let Some(disc_titles) = &changes.disc_titles {
+            self.write_raw_disc_titles(disc_titles.clone()).await?;
+        }
+
+        for change in &changes.track_titles {
+            if let Some(title) = &change.title {
+                self.interface
+                    .set_track_title(change.index, title, false)
+                    .await?;
             }
-            DiscFormat::LP2 | DiscFormat::LP4 => {
-                header = create_wav_header(format, result.len() as u32);
+            if let Some(full_width_title) = &change.full_width_title {
+                self.interface
+                    .set_track_title(change.index, full_width_title, true)
+                    .await?;
             }
         }
 
-        output_vec.extend_from_slice(&header);
-        output_vec.extend_from_slice(&result);
+        // The cached disc no longer matches the device
+        if !changes.is_empty() {
+            self.disc = None;
+        }
 
-        Ok((format, header))
+        Ok(())
     }
 
-    pub async fn prepare_download(&mut self) -> Result<(), Box<dyn Error>> {
-        while ![OperatingStatus::DiscBlank, OperatingStatus::Ready].contains(
-            &self
-                .device_status()
-                .await?
-                .state
-                .unwrap_or(OperatingStatus::NoDisc),
-        ) {
-            cross_sleep(Duration::from_millis(200)).await;
-        }
+    /// Move a track to another index on the disc, keeping the groups intact.
+    ///
+    /// Unlike [`NetMDInterface::move_track`], the group ranges in the disc
+    /// title are updated to follow the tracks to their new positions.
+    pub async fn move_track(&mut self, from: u16, to: u16) -> Result<(), InterfaceError> {
+        self.ensure_writable().await?;
 
-        let _ = self.interface.session_key_forget().await;
-        let _ = self.interface.leave_secure_session().await;
+        let mut disc = self.list_content().await?;
 
-        self.interface.acquire().await?;
-        let _ = self.interface.disable_new_track_protection(1).await;
+        self.interface.move_track(from, to).await?;
+        disc.move_track(from, to);
 
-        Ok(())
+        self.rewrite_disc_groups(disc).await
     }
 
-    /// Start downloading an [`MDTrack`] to the device.
+    /// Erase a track, keeping the groups intact.
     ///
-    /// Progress is updated in the `progress_callback` closure.
-    pub async fn download<F>(
-        &mut self,
-        track: MDTrack,
-        progress_callback: F,
-    ) -> Result<(u16, Vec<u8>, Vec<u8>), Box<dyn Error>>
+    /// Protected tracks are refused with [`InterfaceError::TrackProtected`]
+    /// unless `force` is set.
+    pub async fn erase_track(&mut self, index: u16, force: bool) -> Result<(), InterfaceError> {
+        self.ensure_writable().await?;
+
+        let mut disc = self.list_content_inner(false).await?;
+
+        let Some(track) = disc.track(index) else {
+            return Err(InterfaceError::InvalidTrack {
+                requested: index,
+                count: disc.track_count,
+            });
+        };
+
+        if !force && matches!(track.protected(), TrackFlag::Protected) {
+            return Err(InterfaceError::TrackProtected(index));
+        }
+
+        self.interface.erase_track(index).await?;
+        disc.erase_track(index);
+
+        self.rewrite_disc_groups(disc).await
+    }
+
+    /// Erase several tracks at once, keeping the groups intact.
+    ///
+    /// Erasing a track shifts every track after it down by one, so the
+    /// tracks are erased from the highest index down to keep the remaining
+    /// indices valid. Returns the number of tracks erased.
+    pub async fn erase_tracks(&mut self, mut indices: Vec<u16>) -> Result<usize, InterfaceError> {
+        self.ensure_writable().await?;
+
+        let mut disc = self.list_content().await?;
+
+        if let Some(&requested) = indices.iter().find(|&&i| i >= disc.track_count) {
+            return Err(InterfaceError::InvalidTrack {
+                requested,
+                count: disc.track_count,
+            })?;
+        }
+
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        indices.dedup();
+
+        for &index in &indices {
+            self.interface.erase_track(index).await?;
+            disc.erase_track(index);
+        }
+
+        self.rewrite_disc_groups(disc).await?;
+
+        Ok(indices.len())
+    }
+
+    /// Merge two adjacent groups into one, keeping the title of group `a`.
+    ///
+    /// The groups must be next to each other on the disc, as MiniDisc
+    /// groups can't be discontinuous.
+    pub async fn merge_groups(&mut self, a: u16, b: u16) -> Result<(), InterfaceError> {
+        self.ensure_writable().await?;
+
+        let mut disc = self.list_content().await?;
+        disc.merge_groups(a, b)?;
+
+        self.rewrite_disc_groups(disc).await
+    }
+
+    /// Split a group in two, with the new group starting at track `at`.
+    ///
+    /// The new group is given the same title as the original.
+    pub async fn split_group(&mut self, index: u16, at: u16) -> Result<(), InterfaceError> {
+        self.ensure_writable().await?;
+
+        let mut disc = self.list_content().await?;
+        disc.split_group(index, at)?;
+
+        self.rewrite_disc_groups(disc).await
+    }
+
+    /// Rename a disc while preserving group titles.
+    ///
+    /// This is the way to set the title of a disc, as the group ranges and
+    /// titles are stored in the disc title. Only the title at the start of
+    /// the raw title is replaced, and the full width title is left alone
+    /// if `new_fw_name` is [`None`].
+    pub async fn rename_disc(
+        &mut self,
+        new_name: &str,
+        new_fw_name: Option<&str>,
+    ) -> Result<(), InterfaceError> {
+        self.ensure_writable().await?;
+
+        let new_name = sanitize_half_width_title(new_name);
+        let new_fw_name = new_fw_name.map(sanitize_full_width_title);
+
+        let old_raw_name = self.editable_raw_disc_title(false).await?;
+        let old_raw_fw_name = self.editable_raw_disc_title(true).await?;
+        let old_name = NetMDInterface::disc_title_from_raw(old_raw_name.clone(), false);
+        let old_fw_name = NetMDInterface::disc_title_from_raw(old_raw_fw_name.clone(), true);
+
+        if let Some(new_fw_name) = new_fw_name.filter(|n| n != &old_fw_name) {
+            let new_fw_name_with_groups =
+                replace_raw_disc_title(&old_raw_fw_name, &new_fw_name, true);
+
+            self.interface
+                .set_disc_title(&new_fw_name_with_groups, true)
+                .await?;
+        }
+
+        if new_name == old_name {
+            return Ok(());
+        }
+
+        let new_name_with_groups = replace_raw_disc_title(&old_raw_name, &new_name, false);
+
+        self.interface
+            .set_disc_title(&new_name_with_groups, false)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get a track from the device. This only works with MZ-RH1 devices,
+    /// see [`NetMD::supports_upload`](super::base::NetMD::supports_upload),
+    /// other devices return [`InterfaceError::UploadNotSupported`].
+    ///
+    /// The track is returned with an AEA header for SP tracks, or a WAV
+    /// header for LP tracks.
+    pub async fn upload<F: Fn(usize, usize)>(
+        &mut self,
+        track: u16,
+        progress_callback: Option<F>,
+    ) -> Result<(DiscFormat, Vec<u8>), InterfaceError> {
+        let mut output_vec = Vec::new();
+        let format = self
+            .upload_to(track, &mut output_vec, progress_callback)
+            .await?;
+
+        Ok((format, output_vec))
+    }
+
+    /// Get a track from the device, streaming it into `writer` as it is
+    /// read instead of keeping the whole track in memory. This only works
+    /// with MZ-RH1 devices, like [`NetMDContext::upload`].
+    ///
+    /// The track is written with an AEA header for SP tracks, or a WAV
+    /// header for LP tracks.
+    pub async fn upload_to<W: std::io::Write, F: Fn(usize, usize)>(
+        &mut self,
+        track: u16,
+        mut writer: W,
+        progress_callback: Option<F>,
+    ) -> Result<DiscFormat, InterfaceError> {
+        if !self.interface.device.supports_upload() {
+            return Err(InterfaceError::UploadNotSupported);
+        }
+
+        // The title can't be read once the transfer has started
+        let name = self.interface.track_title(track, false).await?;
+
+        let header = |format: DiscFormat, length: usize| match format {
+            DiscFormat::SPMono | DiscFormat::SPStereo => {
+                let channels = if format == DiscFormat::SPStereo { 2 } else { 1 };
+                let aea_options = AeaOptions {
+                    name: &name,
+                    channels,
+                    sound_groups: aea_sound_groups(length, channels),
+                    ..Default::default()
+                };
+                create_aea_header(aea_options)
+            }
+            DiscFormat::LP2 | DiscFormat::LP4 => create_wav_header(format, length as u32),
+        };
+
+        match self
+            .interface
+            .save_track_to_writer(track, progress_callback, &mut writer, header)
+            .await
+        {
+            Ok((format, _frames)) => Ok(format),
+            Err(error) => {
+                let _ = self.interface.device.reset_endpoints().await;
+                Err(error)
+            }
+        }
+    }
+
+    /// Wait until the device reports one of `states`, returning the state
+    /// it reached, or [`InterfaceError::Timeout`] once `timeout` has passed.
+    pub async fn wait_for_status(
+        &mut self,
+        states: &[OperatingStatus],
+        timeout: Duration,
+    ) -> Result<OperatingStatus, InterfaceError> {
+        let attempts = timeout.as_millis() / Self::STATUS_POLL_INTERVAL.as_millis() + 1;
+
+        for _ in 0..attempts {
+            let state = self
+                .device_status()
+                .await?
+                .state
+                .unwrap_or(OperatingStatus::NoDisc);
+
+            if states.contains(&state) {
+                return Ok(state);
+            }
+
+            cross_sleep(Self::STATUS_POLL_INTERVAL).await;
+        }
+
+        Err(InterfaceError::Timeout)
+    }
+
+    /// Wait until a disc is inserted and its TOC has been read, then list
+    /// its contents, or return [`InterfaceError::Timeout`] once `timeout`
+    /// has passed.
+    pub async fn wait_for_disc(&mut self, timeout: Duration) -> Result<Disc, InterfaceError> {
+        let attempts = timeout.as_millis() / Self::STATUS_POLL_INTERVAL.as_millis() + 1;
+
+        for _ in 0..attempts {
+            let status = self.device_status().await?;
+            let ready = !matches!(
+                status.state,
+                Some(OperatingStatus::ReadingTOC | OperatingStatus::NoDisc)
+            );
+
+            if status.disc_present && ready {
+                return self.list_content_inner(false).await;
+            }
+
+            cross_sleep(Self::STATUS_POLL_INTERVAL).await;
+        }
+
+        Err(InterfaceError::Timeout)
+    }
+
+    pub async fn prepare_download(&mut self) -> Result<(), InterfaceError> {
+        self.wait_for_status(
+            &[
+                OperatingStatus::DiscBlank,
+                OperatingStatus::Ready,
+                OperatingStatus::ReadyForTransfer,
+            ],
+            Self::DOWNLOAD_READY_TIMEOUT,
+        )
+        .await?;
+
+        let _ = self.interface.session_key_forget().await;
+        let _ = self.interface.leave_secure_session().await;
+
+        self.interface.acquire().await?;
+        let _ = self.interface.disable_new_track_protection(1).await;
+
+        Ok(())
+    }
+
+    /// Start downloading an [`MDTrack`] to the device.
+    ///
+    /// Progress is updated in the `progress_callback` closure.
+    ///
+    /// If `verify` is set, the length of the new track is read back from the
+    /// device and compared to the data that was sent, which costs an extra
+    /// round trip.
+    ///
+    /// The track is recorded in the [`DiscFormat`] matching its wire format
+    /// unless `disc_format` is set. To record a mono SP track, send stereo
+    /// [`WireFormat::Pcm`](super::interface::WireFormat::Pcm) data with
+    /// [`DiscFormat::SPMono`].
+    pub async fn download<F>(
+        &mut self,
+        track: MDTrack,
+        progress_callback: F,
+        verify: bool,
+        disc_format: Option<DiscFormat>,
+    ) -> Result<(u16, Vec<u8>, Vec<u8>), InterfaceError>
     where
         F: Fn(usize, usize),
     {
+        self.ensure_writable().await?;
+
+        self.download_unchecked(track, progress_callback, verify, disc_format)
+            .await
+    }
+
+    /// [`NetMDContext::download`] without checking that the disc is
+    /// writable first
+    async fn download_unchecked<F>(
+        &mut self,
+        track: MDTrack,
+        progress_callback: F,
+        verify: bool,
+        disc_format: Option<DiscFormat>,
+    ) -> Result<(u16, Vec<u8>, Vec<u8>), InterfaceError>
+    where
+        F: Fn(usize, usize),
+    {
+        if track.is_empty() {
+            return Err(InterfaceError::EmptyTrack);
+        }
+
+        let format = track.data_format();
+        let expected_frames = track.frame_count();
+        let frames_per_second = format.frames_in(RawTime::from_frames(512));
+
+        let mut session = self.start_session_unchecked().await?;
+        let result =
+            Self::download_with_session(&mut session, track, progress_callback, disc_format)
+                .await?;
+        session.close().await?;
+        self.interface.release().await?;
+
+        if verify {
+            let length = self.interface.track_length(result.0).await?;
+            let actual_frames = format.frames_in(length);
+
+            // Allow up to a second of difference for rounding on the device
+            if expected_frames.abs_diff(actual_frames) > frames_per_second {
+                return Err(InterfaceError::DownloadVerificationFailed {
+                    expected: expected_frames,
+                    actual: actual_frames,
+                });
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Prepare the device for downloading and start a secure session which
+    /// the caller manages, for flows which need the same session across
+    /// several steps, or to inspect it with [`MDSession::info`].
+    ///
+    /// Tracks are downloaded with [`NetMDContext::download_with_session`].
+    /// Once done, close the session with [`MDSession::close`] and release
+    /// the device with [`NetMDInterface::release`].
+    pub async fn start_session(&mut self) -> Result<MDSession<'_>, InterfaceError> {
+        self.ensure_writable().await?;
+
+        self.start_session_unchecked().await
+    }
+
+    /// [`NetMDContext::start_session`] without checking that the disc is
+    /// writable first
+    async fn start_session_unchecked(&mut self) -> Result<MDSession<'_>, InterfaceError> {
         self.prepare_download().await?;
+
         // Lock the interface by providing it to the session
         let mut session = MDSession::new(&mut self.interface);
         session.init().await?;
+
+        Ok(session)
+    }
+
+    /// Download an [`MDTrack`] in a session from [`NetMDContext::start_session`],
+    /// returning the index, UUID and content ID of the new track.
+    ///
+    /// The USB endpoints are reset if the download fails, so the session
+    /// can be closed afterwards.
+    pub async fn download_with_session<F>(
+        session: &mut MDSession<'_>,
+        track: MDTrack,
+        progress_callback: F,
+        disc_format: Option<DiscFormat>,
+    ) -> Result<(u16, Vec<u8>, Vec<u8>), InterfaceError>
+    where
+        F: Fn(usize, usize),
+    {
         let result = session
-            .download_track(track, progress_callback, None)
+            .download_track(track, progress_callback, disc_format)
+            .await;
+        if result.is_err() {
+            let _ = session.md.device.reset_endpoints().await;
+        }
+
+        result
+    }
+
+    /// Replace the audio of a track, keeping its title and group.
+    ///
+    /// The new track is downloaded before the old one is erased, so the
+    /// disc needs enough room for both. If the download fails, the disc is
+    /// left untouched, and if the new track can't be moved into place it is
+    /// erased again. If the old track can't be erased afterwards, both
+    /// tracks stay on the disc and [`InterfaceError::ReplaceIncomplete`] is
+    /// returned with the index of the old track.
+    pub async fn replace_track<F>(
+        &mut self,
+        index: u16,
+        new_track: MDTrack,
+        progress_callback: F,
+    ) -> Result<(), InterfaceError>
+    where
+        F: Fn(usize, usize),
+    {
+        self.ensure_writable().await?;
+
+        let disc = self.list_content().await?;
+        let Some(old_track) = disc.track(index) else {
+            return Err(InterfaceError::InvalidTrack {
+                requested: index,
+                count: disc.track_count,
+            });
+        };
+
+        // The new track is always added at the end of the disc
+        let (new_index, _, _) = self
+            .download_unchecked(new_track, progress_callback, false, None)
             .await?;
-        session.close().await?;
-        self.interface.release().await?;
 
-        Ok(result)
+        if let Err(error) = self.interface.move_track(new_index, index).await {
+            let _ = self.interface.erase_track(new_index).await;
+            return Err(error);
+        }
+
+        // The old track was moved up by one
+        if let Err(error) = self.interface.erase_track(index + 1).await {
+            return Err(InterfaceError::ReplaceIncomplete {
+                index: index + 1,
+                source: Box::new(error),
+            });
+        }
+
+        self.interface
+            .set_track_title(index, &old_track.title, false)
+            .await?;
+        self.interface
+            .set_track_title(index, &old_track.full_width_title, true)
+            .await?;
+
+        // The track order is the same as before, so the original groups
+        // still apply
+        self.rewrite_disc_groups(disc).await?;
+        self.list_content().await?;
+
+        Ok(())
+    }
+
+    /// Preview the raw half and full width titles which would be written
+    /// for `disc`, without writing anything to the device.
+    ///
+    /// The flag is set when any title had to be dropped because it didn't
+    /// fit in the TOC.
+    pub fn preview_disc_titles(&self, disc: &Disc) -> (String, String, bool) {
+        disc.compile_disc_titles_checked()
+    }
+
+    /// Check that the disc can be edited, using the flags of the cached
+    /// listing if there is one.
+    async fn ensure_writable(&mut self) -> Result<(), InterfaceError> {
+        let (writeable, write_protected) = match &self.disc {
+            Some(disc) => (disc.writeable, disc.write_protected),
+            None => {
+                let flags = self.interface.disc_flags().await?;
+                (
+                    flags & DiscFlag::Writable as u8 != 0,
+                    flags & DiscFlag::WriteProtected as u8 != 0,
+                )
+            }
+        };
+
+        check_writable(writeable, write_protected)
+    }
+
+    /// Force the device to write its TOC to the disc, making any edits
+    /// made so far persistent without ejecting the disc.
+    pub async fn commit_changes(&mut self) -> Result<(), InterfaceError> {
+        self.interface.flush_toc().await
+    }
+
+    /// Erase the disc and write the empty TOC, then check that it took.
+    ///
+    /// Discs which aren't writable or are write protected are refused
+    /// before anything is sent to the device.
+    pub async fn format_disc(&mut self) -> Result<(), InterfaceError> {
+        let flags = self.interface.disc_flags().await?;
+        if flags & DiscFlag::WriteProtected as u8 != 0 {
+            return Err(InterfaceError::WriteProtected);
+        }
+        if flags & DiscFlag::Writable as u8 == 0 {
+            return Err(InterfaceError::DiscNotWritable);
+        }
+
+        self.interface.erase_disc().await?;
+        self.disc = None;
+        self.listing_fingerprint = None;
+
+        // Devices which can't flush the TOC on command write it on eject
+        match self.commit_changes().await {
+            Ok(_) | Err(InterfaceError::Rejected(_) | InterfaceError::NotImplemented(_)) => (),
+            Err(error) => return Err(error),
+        }
+
+        let track_count = self.interface.track_count().await?;
+        if track_count != 0 || !self.interface.raw_disc_title(false).await?.is_empty() {
+            return Err(InterfaceError::FormatFailed { track_count });
+        }
+
+        Ok(())
+    }
+
+    /// Write the TOC to the disc, then eject it.
+    ///
+    /// Some devices lose unsaved edits when ejecting, so the TOC is always
+    /// written first. Devices without an eject mechanism return
+    /// [`InterfaceError::EjectNotSupported`].
+    pub async fn eject(&mut self) -> Result<(), InterfaceError> {
+        if !self.interface.can_eject_disc().await? {
+            return Err(InterfaceError::EjectNotSupported);
+        }
+
+        // Devices which can't flush the TOC on command write it on eject
+        match self.commit_changes().await {
+            Ok(_) | Err(InterfaceError::Rejected(_) | InterfaceError::NotImplemented(_)) => (),
+            Err(error) => return Err(error),
+        }
+
+        self.interface.eject_disc().await?;
+        self.disc = None;
+        self.listing_fingerprint = None;
+
+        Ok(())
+    }
+
+    /// Get the device code in a readable form, such as `S1.200`.
+    ///
+    /// This opens and authenticates the factory interface of the device,
+    /// which is left authenticated afterwards. Devices without a factory
+    /// interface return an error.
+    pub async fn device_code(&mut self) -> Result<String, InterfaceError> {
+        let mut factory_interface = self.interface.factory().await?;
+
+        get_descriptive_device_code(&mut factory_interface).await
+    }
+
+    /// Collect the raw state of the disc and device into a [`DiagnosticReport`].
+    ///
+    /// This reads everything from the device and doesn't use or change the
+    /// cached listing. The device code is only read if `include_device_code`
+    /// is set, as that opens the factory interface, see
+    /// [`NetMDContext::device_code`].
+    pub async fn dump_diagnostics(
+        &mut self,
+        include_device_code: bool,
+    ) -> Result<DiagnosticReport, InterfaceError> {
+        let device = &self.interface.device;
+        let device_name = device.device_name().map(str::to_string);
+        let (vendor_id, product_id) = (device.vendor_id(), device.product_id());
+
+        let disc_flags = self.interface.disc_flags().await?;
+        let track_count = self.interface.track_count().await?;
+        let raw_disc_title = self.interface.raw_disc_title_bytes(false).await?;
+        let raw_full_width_disc_title = self.interface.raw_disc_title_bytes(true).await?;
+
+        let indices: Vec<u16> = (0..track_count).collect();
+        let mut tracks = Vec::with_capacity(indices.len());
+        if track_count > 0 {
+            let titles = self
+                .interface
+                .track_title_bytes(indices.clone(), false)
+                .await?;
+            let full_width_titles = self
+                .interface
+                .track_title_bytes(indices.clone(), true)
+                .await?;
+            let flags = self.interface.track_flags_list(indices.clone()).await?;
+
+            for (i, index) in indices.into_iter().enumerate() {
+                tracks.push(TrackDiagnostics {
+                    index,
+                    title: titles[i].clone(),
+                    full_width_title: full_width_titles[i].clone(),
+                    flags: flags[i],
+                });
+            }
+        }
+
+        // Not every device has a factory interface
+        let device_code = match include_device_code {
+            true => self.device_code().await.ok(),
+            false => None,
+        };
+
+        Ok(DiagnosticReport {
+            device_name,
+            vendor_id,
+            product_id,
+            device_code,
+            disc_flags,
+            track_count,
+            raw_disc_title,
+            raw_full_width_disc_title,
+            tracks,
+        })
     }
 
     /// Get a reference to the underlying interface.
@@ -576,15 +1707,529 @@ impl NetMDContext {
     pub fn interface_mut(&mut self) -> &mut NetMDInterface {
         &mut self.interface
     }
+
+    /// Consume the context and return the underlying interface.
+    ///
+    /// A context can be rebuilt from the interface later using
+    /// [`NetMDContext::from`].
+    pub fn into_interface(self) -> NetMDInterface {
+        self.interface
+    }
 }
 
 impl From<NetMDInterface> for NetMDContext {
     /// Create a context from an already opened interface.
     fn from(value: NetMDInterface) -> Self {
-        Self { interface: value }
+        Self {
+            interface: value,
+            disc: None,
+            listing_fingerprint: None,
+        }
+    }
+}
+
+/// Check the writable and write protected flags of a disc before editing it
+fn check_writable(writeable: bool, write_protected: bool) -> Result<(), InterfaceError> {
+    if write_protected {
+        return Err(InterfaceError::WriteProtected);
+    }
+    if !writeable {
+        return Err(InterfaceError::DiscNotWritable);
+    }
+
+    Ok(())
+}
+
+/// Identify the state of a disc by its track count and the bytes of its raw
+/// half width title, which holds the groups
+fn disc_fingerprint(track_count: u16, raw_title: &[u8]) -> (u16, u64) {
+    let mut hasher = DefaultHasher::new();
+    raw_title.hash(&mut hasher);
+
+    (track_count, hasher.finish())
+}
+
+/// Replace the disc title at the start of a raw disc title with `new_name`,
+/// keeping the groups which follow it
+fn replace_raw_disc_title(old_raw_name: &str, new_name: &str, full_width: bool) -> String {
+    let (prefix, separator) = match full_width {
+        true => ("０；", "／／"),
+        false => ("0;", "//"),
+    };
+
+    if !old_raw_name.contains(separator) {
+        return new_name.to_string();
+    }
+
+    if old_raw_name.starts_with(prefix) {
+        let re = match full_width {
+            true => Regex::new(r"^０；.*?／／").unwrap(),
+            false => Regex::new(r"^0;.*?\/\/").unwrap(),
+        };
+        let title = match new_name.is_empty() {
+            true => String::new(),
+            false => format!("{prefix}{new_name}{separator}"),
+        };
+        re.replace_all(old_raw_name, title).into()
+    } else {
+        format!("{prefix}{new_name}{separator}{old_raw_name}")
     }
 }
 
+/// Titles in the TOC are stored in cells of 7 bytes, with a limit of
+/// [`TITLE_CELL_LIMIT`] cells shared between every title on the disc.
+/// A title of `len` bytes takes up `len / 7` cells, rounded up.
 fn chars_to_cells(len: usize) -> usize {
     f32::ceil(len as f32 / 7.0) as usize
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(index: u16) -> Track {
+        Track {
+            index,
+            title: format!("Track {}", index + 1),
+            full_width_title: String::new(),
+            duration: RawTime::from_frames(512 * 60),
+            channel: Channels::Stereo,
+            encoding: Encoding::SP,
+            protected: TrackFlag::Unprotected,
+            flags: 0,
+        }
+    }
+
+    /// A disc laid out like [`NetMDContext::list_content`] does, with the
+    /// given groups and every other track in its own untitled group
+    fn disc(track_count: u16, groups: &[(&str, std::ops::Range<u16>)]) -> Disc {
+        let mut disc_groups: Vec<Group> = (0..track_count)
+            .filter(|i| !groups.iter().any(|(_, range)| range.contains(i)))
+            .map(|i| Group {
+                index: 0,
+                title: None,
+                full_width_title: None,
+                tracks: vec![track(i)],
+            })
+            .collect();
+
+        disc_groups.extend(groups.iter().map(|(title, range)| Group {
+            index: 0,
+            title: Some(title.to_string()),
+            full_width_title: None,
+            tracks: range.clone().map(track).collect(),
+        }));
+
+        let mut disc = Disc {
+            title: String::from("Disc"),
+            full_width_title: String::new(),
+            writeable: true,
+            write_protected: false,
+            used: 0,
+            left: 512 * 60 * 80,
+            total: 512 * 60 * 80,
+            track_count,
+            groups: disc_groups,
+            invalid_raw_title: None,
+        };
+        disc.renumber_groups();
+
+        disc
+    }
+
+    #[test]
+    fn chars_to_cells_rounds_up() {
+        assert_eq!(chars_to_cells(0), 0);
+        assert_eq!(chars_to_cells(1), 1);
+        assert_eq!(chars_to_cells(7), 1);
+        assert_eq!(chars_to_cells(8), 2);
+        assert_eq!(chars_to_cells(14), 2);
+        assert_eq!(chars_to_cells(15), 3);
+        assert_eq!(chars_to_cells(TITLE_CELL_LIMIT * 7), TITLE_CELL_LIMIT);
+    }
+
+    #[test]
+    fn cells_for_title() {
+        let mut track = track(0);
+
+        track.title = String::from("1234567");
+        assert_eq!(track.cells_for_title(), (1, 0));
+
+        // Two extra bytes for the dakuten push this into a second cell
+        track.title = String::from("ガギグ7");
+        assert_eq!(track.cells_for_title(), (1, 0));
+        track.title = String::from("ガギグ78");
+        assert_eq!(track.cells_for_title(), (2, 0));
+
+        // Full width titles take 2 bytes per character
+        track.full_width_title = String::from("ABCD");
+        assert_eq!(track.cells_for_title(), (2, 2));
+    }
+
+    #[test]
+    fn best_encoding_at_each_boundary() {
+        let minute = 512 * 60;
+        let mut disc = disc(3, &[]);
+        disc.left = 10 * minute;
+
+        for (frames, encoding) in [
+            (0, Some(Encoding::SP)),
+            (10 * minute, Some(Encoding::SP)),
+            (10 * minute + 1, Some(Encoding::LP2)),
+            (20 * minute, Some(Encoding::LP2)),
+            (20 * minute + 1, Some(Encoding::LP4)),
+            (40 * minute, Some(Encoding::LP4)),
+            (40 * minute + 1, None),
+        ] {
+            assert_eq!(
+                disc.best_encoding_for(RawTime::from_frames(frames)),
+                encoding,
+                "{} frames",
+                frames
+            );
+        }
+
+        // Nothing fits on a full disc
+        disc.left = 0;
+        assert_eq!(disc.best_encoding_for(RawTime::from_frames(1)), None);
+    }
+
+    #[test]
+    fn durations_of_disc_and_track() {
+        let mut disc = disc(3, &[]);
+        disc.used = 512 * 60 * 3;
+        disc.left = 512 * 60 * 77;
+
+        assert_eq!(disc.used_duration(), Duration::from_secs(3 * 60));
+        assert_eq!(disc.left_duration(), Duration::from_secs(77 * 60));
+        assert_eq!(disc.capacity_duration(), Duration::from_secs(80 * 60));
+        assert_eq!(
+            disc.track(0).unwrap().duration_std(),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn duration_by_encoding() {
+        assert!(disc(0, &[]).duration_by_encoding().is_empty());
+
+        let mut disc = disc(4, &[("A", 1..3)]);
+        for (index, encoding) in [(1, Encoding::LP2), (2, Encoding::LP2), (3, Encoding::LP4)] {
+            disc.track_mut(index).unwrap().encoding = encoding;
+        }
+
+        let durations = disc.duration_by_encoding();
+        assert_eq!(durations.len(), 3);
+        assert_eq!(durations[&Encoding::SP], RawTime::from_frames(512 * 60));
+        assert_eq!(durations[&Encoding::LP2], RawTime::from_frames(512 * 120));
+        assert_eq!(durations[&Encoding::LP4], RawTime::from_frames(512 * 60));
+    }
+
+    #[test]
+    fn diff_unchanged_disc() {
+        let disc = disc(6, &[("A", 0..3)]);
+
+        let changes = disc.diff(&disc.clone());
+        assert!(changes.is_empty());
+        assert_eq!(changes, DiscChanges::default());
+    }
+
+    #[test]
+    fn diff_renamed_track() {
+        // A disc which already has a full width title, so that changing
+        // one doesn't add full width group titles to the disc title
+        let mut disc = disc(6, &[("A", 0..3)]);
+        disc.track_mut(4).unwrap().full_width_title = String::from("オールド");
+        let mut renamed = disc.clone();
+        renamed.track_mut(1).unwrap().title = String::from("New");
+        renamed.track_mut(4).unwrap().full_width_title = String::from("ニュー");
+
+        assert_eq!(
+            disc.diff(&renamed),
+            DiscChanges {
+                disc_titles: None,
+                track_titles: vec![
+                    TrackTitleChange {
+                        index: 1,
+                        title: Some(String::from("New")),
+                        full_width_title: None,
+                    },
+                    TrackTitleChange {
+                        index: 4,
+                        title: None,
+                        full_width_title: Some(String::from("ニュー")),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn diff_first_full_width_title() {
+        // The full width disc title holds the groups again once any full
+        // width title is set, so it has to be written as well
+        let disc = disc(6, &[("A", 0..3)]);
+        let mut renamed = disc.clone();
+        renamed.track_mut(4).unwrap().full_width_title = String::from("ニュー");
+
+        let changes = disc.diff(&renamed);
+        assert_eq!(
+            changes.disc_titles,
+            Some((
+                String::from("0;Disc//1-3;A//"),
+                String::from("０；／／１－３；／／")
+            ))
+        );
+        assert_eq!(changes.track_titles.len(), 1);
+    }
+
+    #[test]
+    fn diff_renamed_disc() {
+        let disc = disc(6, &[("A", 0..3)]);
+        let mut renamed = disc.clone();
+        renamed.title = String::from("New");
+
+        let changes = disc.diff(&renamed);
+        assert_eq!(
+            changes
+                .disc_titles
+                .as_ref()
+                .map(|(title, _)| title.as_str()),
+            Some("0;New//1-3;A//")
+        );
+        assert!(changes.track_titles.is_empty());
+    }
+
+    #[test]
+    fn diff_changed_group() {
+        let disc = disc(6, &[("A", 0..3), ("B", 3..6)]);
+        let mut merged = disc.clone();
+        merged.merge_groups(0, 1).unwrap();
+
+        let changes = disc.diff(&merged);
+        assert_eq!(
+            changes
+                .disc_titles
+                .as_ref()
+                .map(|(title, _)| title.as_str()),
+            Some("0;Disc//1-6;A//")
+        );
+        assert!(changes.track_titles.is_empty());
+    }
+
+    /// The titled groups of a disc and the indices of their tracks
+    fn group_ranges(disc: &Disc) -> Vec<(&str, Vec<u16>)> {
+        disc.groups()
+            .iter()
+            .filter_map(|g| Some((g.title()?, g.tracks().iter().map(|t| t.index).collect())))
+            .collect()
+    }
+
+    #[test]
+    fn merge_adjacent_groups() {
+        let groups = [("A", 0..3), ("B", 3..6), ("C", 6..9)];
+        let mut reversed = disc(9, &groups);
+        let mut disc = disc(9, &groups);
+        disc.merge_groups(0, 1).unwrap();
+
+        assert_eq!(
+            group_ranges(&disc),
+            vec![("A", vec![0, 1, 2, 3, 4, 5]), ("C", vec![6, 7, 8])]
+        );
+        assert_eq!(disc.compile_disc_titles().0, "0;Disc//1-6;A//7-9;C//");
+
+        // The title of the first group given is kept, wherever it is
+        reversed.merge_groups(2, 1).unwrap();
+
+        assert_eq!(
+            group_ranges(&reversed),
+            vec![("A", vec![0, 1, 2]), ("C", vec![3, 4, 5, 6, 7, 8])]
+        );
+    }
+
+    #[test]
+    fn merge_invalid_groups() {
+        let mut disc = disc(9, &[("A", 0..3), ("B", 3..6), ("C", 6..9)]);
+        let before = disc.clone();
+
+        for (a, b) in [(0, 2), (2, 0), (1, 1), (0, 3), (7, 1)] {
+            assert!(
+                matches!(disc.merge_groups(a, b), Err(InterfaceError::GroupError(_))),
+                "merging {} and {}",
+                a,
+                b
+            );
+        }
+
+        assert_eq!(group_ranges(&disc), group_ranges(&before));
+    }
+
+    #[test]
+    fn split_group_at_each_end() {
+        let mut disc = disc(9, &[("A", 0..3), ("B", 3..6), ("C", 6..9)]);
+
+        // The second and last tracks are the only boundaries in a group
+        // of three
+        disc.split_group(1, 4).unwrap();
+        assert_eq!(
+            group_ranges(&disc),
+            vec![
+                ("A", vec![0, 1, 2]),
+                ("B", vec![3]),
+                ("B", vec![4, 5]),
+                ("C", vec![6, 7, 8])
+            ]
+        );
+
+        disc.split_group(3, 8).unwrap();
+        assert_eq!(
+            group_ranges(&disc),
+            vec![
+                ("A", vec![0, 1, 2]),
+                ("B", vec![3]),
+                ("B", vec![4, 5]),
+                ("C", vec![6, 7]),
+                ("C", vec![8])
+            ]
+        );
+        assert_eq!(
+            disc.compile_disc_titles().0,
+            "0;Disc//1-3;A//4;B//5-6;B//7-8;C//9;C//"
+        );
+    }
+
+    #[test]
+    fn split_group_at_invalid_track() {
+        let mut disc = disc(9, &[("A", 0..3), ("B", 3..6), ("C", 6..9)]);
+
+        // Splitting at the first track would leave an empty group
+        for (group, at) in [(1, 3), (1, 2), (1, 6), (1, 100), (3, 1), (9, 4)] {
+            assert!(
+                matches!(
+                    disc.split_group(group, at),
+                    Err(InterfaceError::GroupError(_))
+                ),
+                "splitting group {} at {}",
+                group,
+                at
+            );
+        }
+        assert_eq!(disc.groups().len(), 3);
+    }
+
+    #[test]
+    fn move_track_between_groups() {
+        let mut disc = disc(9, &[("A", 0..3), ("B", 3..6), ("C", 6..9)]);
+        let moved_title = disc.track(1).unwrap().title.clone();
+
+        disc.move_track(1, 7);
+
+        assert_eq!(
+            group_ranges(&disc),
+            vec![
+                ("A", vec![0, 1]),
+                ("B", vec![2, 3, 4]),
+                ("C", vec![5, 6, 7, 8])
+            ]
+        );
+        assert_eq!(disc.track(7).unwrap().title, moved_title);
+        assert_eq!(disc.track_count(), 9);
+        assert_eq!(
+            disc.compile_disc_titles().0,
+            "0;Disc//1-2;A//3-5;B//6-9;C//"
+        );
+    }
+
+    #[test]
+    fn move_track_backwards_out_of_group() {
+        let mut disc = disc(7, &[("A", 1..3), ("B", 3..5), ("C", 5..7)]);
+
+        disc.move_track(6, 0);
+
+        assert_eq!(
+            group_ranges(&disc),
+            vec![("A", vec![2, 3]), ("B", vec![4, 5]), ("C", vec![6])]
+        );
+        assert_eq!(disc.group_of(0).unwrap().title(), None);
+        assert_eq!(disc.group_of(1).unwrap().title(), None);
+    }
+
+    #[test]
+    fn disc_usage_at_95_percent() {
+        let mut disc = disc(0, &[]);
+        disc.used = disc.total * 95 / 100;
+        disc.left = disc.total - disc.used;
+
+        assert!((disc.fraction_used() - 0.95).abs() < 1e-6);
+        assert!(!disc.is_full());
+
+        // A sound group of 512 samples is about 5.9 512ths of a second
+        disc.left = 5;
+        disc.used = disc.total - disc.left;
+        assert!(disc.is_full());
+        disc.left = 6;
+        assert!(!disc.is_full());
+
+        disc.total = 0;
+        assert_eq!(disc.fraction_used(), 0.0);
+    }
+
+    #[test]
+    fn rename_grouped_disc_keeps_groups() {
+        let old = "0;Disc//1-3;A//4-5;B//";
+        assert_eq!(
+            replace_raw_disc_title(old, "New", false),
+            "0;New//1-3;A//4-5;B//"
+        );
+        assert_eq!(replace_raw_disc_title(old, "", false), "1-3;A//4-5;B//");
+
+        // Groups without a disc title
+        assert_eq!(
+            replace_raw_disc_title("1-3;A//", "New", false),
+            "0;New//1-3;A//"
+        );
+
+        // Without groups the raw title is the title
+        assert_eq!(replace_raw_disc_title("Disc", "New", false), "New");
+    }
+
+    #[test]
+    fn rename_grouped_disc_keeps_full_width_groups() {
+        let old = "０；ディスク／／１－３；Ａ／／";
+        assert_eq!(
+            replace_raw_disc_title(old, "ニュー", true),
+            "０；ニュー／／１－３；Ａ／／"
+        );
+        assert_eq!(
+            replace_raw_disc_title("１－３；Ａ／／", "ニュー", true),
+            "０；ニュー／／１－３；Ａ／／"
+        );
+    }
+
+    #[test]
+    fn disc_fingerprint_changes_with_title_and_track_count() {
+        let fingerprint = disc_fingerprint(3, b"0;Disc//1-3;A//");
+
+        assert_eq!(disc_fingerprint(3, b"0;Disc//1-3;A//"), fingerprint);
+        assert_ne!(disc_fingerprint(3, b"0;Disc//1-2;A//"), fingerprint);
+        assert_ne!(disc_fingerprint(4, b"0;Disc//1-3;A//"), fingerprint);
+    }
+
+    #[test]
+    fn write_protected_disc_is_refused() {
+        let mut disc = disc(3, &[]);
+        assert!(check_writable(disc.writeable, disc.write_protected).is_ok());
+
+        disc.write_protected = true;
+        assert!(matches!(
+            check_writable(disc.writeable, disc.write_protected),
+            Err(InterfaceError::WriteProtected)
+        ));
+
+        // A disc without the writable flag
+        assert!(matches!(
+            check_writable(false, false),
+            Err(InterfaceError::DiscNotWritable)
+        ));
+    }
+}
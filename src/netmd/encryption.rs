@@ -1,26 +1,101 @@
 use cbc::cipher::block_padding::NoPadding;
 use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyInit, KeyIvInit};
+use futures_core::Stream;
 use rand::RngCore;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use std::thread;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
 
 use super::interface::DataEncryptorInput;
 
 type DesEcbEnc = ecb::Decryptor<des::Des>;
+type DesEcbDec = ecb::Encryptor<des::Des>;
 type DesCbcEnc = cbc::Encryptor<des::Des>;
 
 pub fn new_thread_encryptor(
     _input: DataEncryptorInput,
+) -> UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    // Create the random key
+    let mut random_key = [0u8; 8];
+    rand::thread_rng().fill_bytes(&mut random_key);
+
+    spawn_encryptor(_input, random_key, 0, 0, [0u8; 8])
+}
+
+/// Resume encrypting a track part way through, after `packet_count` packets
+/// covering `offset` bytes of the input data have already been sent.
+///
+/// `encrypted_key` is the key sent with the first packet, and `iv` is the
+/// last 8 bytes of the last packet which was sent, as packets are chained
+/// together in the same way as DES-CBC blocks.
+pub fn resume_thread_encryptor(
+    input: DataEncryptorInput,
+    encrypted_key: [u8; 8],
+    offset: usize,
+    packet_count: u32,
+    iv: [u8; 8],
+) -> UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    // The key is "encrypted" by decrypting it with the kek, so encrypting
+    // it recovers the original key
+    let mut random_key = encrypted_key;
+    DesEcbDec::new(&input.kek.into())
+        .encrypt_padded_mut::<NoPadding>(&mut random_key, 8)
+        .unwrap();
+
+    spawn_encryptor(input, random_key, offset, packet_count, iv)
+}
+
+/// A [`Stream`] of the encrypted packets from an encryptor, as `(key, iv, data)`.
+///
+/// This allows the packets to be used with stream combinators, the
+/// receiver can be taken back out with [`PacketStream::into_inner`] to pass
+/// it to [`NetMDInterface::send_track`](super::interface::NetMDInterface::send_track).
+pub struct PacketStream {
+    receiver: UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)>,
+}
+
+impl PacketStream {
+    pub fn new(receiver: UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)>) -> Self {
+        Self { receiver }
+    }
+
+    /// Stop the encryptor from producing any more packets
+    pub fn close(&mut self) {
+        self.receiver.close()
+    }
+
+    pub fn into_inner(self) -> UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+        self.receiver
+    }
+}
+
+impl From<UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)>> for PacketStream {
+    fn from(receiver: UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)>) -> Self {
+        Self::new(receiver)
+    }
+}
+
+impl Stream for PacketStream {
+    type Item = (Vec<u8>, Vec<u8>, Vec<u8>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+fn spawn_encryptor(
+    _input: DataEncryptorInput,
+    random_key: [u8; 8],
+    offset: usize,
+    packet_count: u32,
+    iv: [u8; 8],
 ) -> UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)> {
     let (tx, rx) = unbounded_channel::<(Vec<u8>, Vec<u8>, Vec<u8>)>();
     let input = Box::from(_input);
 
     thread::spawn(move || {
-        let mut iv = [0u8; 8];
-
-        // Create the random key
-        let mut random_key = [0u8; 8];
-        rand::thread_rng().fill_bytes(&mut random_key);
+        let mut iv = iv;
 
         // Encrypt it with the kek
         let mut encrypted_random_key = random_key;
@@ -35,18 +110,18 @@ pub fn new_thread_encryptor(
             e => e,
         };
 
-        let mut packet_count = 0u32;
+        let mut packet_count = packet_count;
         let mut current_chunk_size;
 
+        // Pad to a whole number of frames, the same as `MDTrack::total_size`
         let mut input_data = input.data.clone();
-        if (input_data.len() % input.frame_size) != 0 {
-            let padding_remaining = input.frame_size - (input_data.len() % input.frame_size);
-            input_data.extend(std::iter::repeat(0).take(padding_remaining));
-        }
+        input_data.resize(input_data.len().next_multiple_of(input.frame_size), 0);
         let input_data_length = input_data.len();
 
-        let mut offset: usize = 0;
+        let mut offset: usize = offset;
         while offset < input_data_length {
+            // The first packet has the 24 byte header prepended by `send_track`,
+            // so it carries less data to keep every packet the same size
             if packet_count > 0 {
                 current_chunk_size = default_chunk_size;
             } else {
@@ -0,0 +1,208 @@
+//! Higher level functions built on top of the [`NetMDFactoryInterface`].
+
+use crate::netmd::factory_interface::{DeviceCode, NetMDFactoryInterface};
+use crate::netmd::interface::InterfaceError;
+
+/// Get the device code in a readable form, such as `S1.200`
+///
+/// The letter describes the chip type, followed by the firmware
+/// version and subversion.
+pub async fn get_descriptive_device_code(
+    factory_interface: &mut NetMDFactoryInterface<'_>,
+) -> Result<String, InterfaceError> {
+    let DeviceCode {
+        chip_type,
+        version,
+        subversion,
+        ..
+    } = factory_interface.get_device_code().await?;
+
+    let code = match chip_type {
+        0x20 => "R".to_string(),
+        0x21 => "S".to_string(),
+        0x22 => "Hn".to_string(),
+        0x24 => "Hr".to_string(),
+        0x25 => "Hx".to_string(),
+        c => format!("? 0x{:02x} ", c),
+    };
+
+    Ok(format!(
+        "{}{}.{}{:02x}",
+        code,
+        version >> 4,
+        version & 0xf,
+        subversion
+    ))
+}
+
+/// Show a string on the display of the device, see
+/// [`NetMDFactoryInterface::set_display_override_string`]
+pub async fn display_string(
+    factory_interface: &mut NetMDFactoryInterface<'_>,
+    text: &str,
+    blink: bool,
+) -> Result<(), InterfaceError> {
+    factory_interface
+        .set_display_override_string(text, blink)
+        .await
+}
+
+/// The size of a single UTOC sector in bytes.
+///
+/// MiniDisc sectors use the CD-ROM XA layout of IEC 61909: 12 sync bytes,
+/// a 4 byte header and 2336 bytes of data. Sectors are read whole, so the
+/// offsets into a sector below count from the first sync byte.
+pub const UTOC_SECTOR_SIZE: usize = 2352;
+
+/// The number of sectors making up the UTOC.
+///
+/// The UTOC of IEC 61909 has sectors 0 to 2: the track map, the titles
+/// and the recording dates. Sector 3 is reserved and sector 4 holds the
+/// full width titles, which were added in a later revision of the format.
+pub const UTOC_SECTOR_COUNT: u16 = 5;
+
+/// The number of bytes read in each peripheral access. Larger reads
+/// haven't been tried on a device.
+const UTOC_CHUNK_SIZE: usize = 0x10;
+
+/// Read a single sector of the UTOC
+pub async fn read_utoc_sector(
+    factory_interface: &mut NetMDFactoryInterface<'_>,
+    sector: u16,
+) -> Result<Vec<u8>, InterfaceError> {
+    let mut sector_data = Vec::with_capacity(UTOC_SECTOR_SIZE);
+
+    for offset in (0..UTOC_SECTOR_SIZE).step_by(UTOC_CHUNK_SIZE) {
+        let chunk = factory_interface
+            .read_metadata_peripheral(sector, offset as u16, UTOC_CHUNK_SIZE as u8)
+            .await?;
+        sector_data.extend_from_slice(&chunk);
+    }
+
+    Ok(sector_data)
+}
+
+/// The offset of the pointer to the first free area entry in UTOC sector 0.
+///
+/// The part pointers P-DFA, P-EMPTY and P-FRA follow the disc information
+/// at 0x30, 0x31 and 0x32. This layout hasn't been checked against the
+/// text of IEC 61909 or a dump of a real disc yet.
+const UTOC_FREE_AREA_POINTER: usize = 0x32;
+
+/// The offset of the table of 8 byte part entries in UTOC sector 0.
+///
+/// Part entries are numbered from 1 to 255 and entry 0 is never used, so
+/// the last entry ends exactly at the end of the sector. Like
+/// [`UTOC_FREE_AREA_POINTER`], this hasn't been checked against a dump
+/// of a real disc yet.
+const UTOC_PARTS_TABLE: usize = 0x130;
+
+/// Get the free areas of the disc from UTOC sector 0 as `(start, end)` pairs.
+///
+/// Addresses are the packed 24 bit UTOC addresses of 14 bits of cluster,
+/// 6 bits of sector and 4 bits of sound group, which order the same as the
+/// positions on the disc. The areas are in the order of the UTOC's linked
+/// list, not sorted.
+pub fn utoc_free_areas(sector: &[u8]) -> Result<Vec<(u32, u32)>, InterfaceError> {
+    if sector.len() != UTOC_SECTOR_SIZE {
+        return Err(InterfaceError::InvalidUtocLength(sector.len()));
+    }
+
+    let address = |offset: usize| {
+        u32::from_be_bytes([0, sector[offset], sector[offset + 1], sector[offset + 2]])
+    };
+
+    let mut areas = Vec::new();
+    let mut visited = [false; 256];
+    let mut entry = sector[UTOC_FREE_AREA_POINTER] as usize;
+
+    // Entry 0 ends the list, and a corrupt UTOC could link back on itself
+    while entry != 0 && !visited[entry] {
+        visited[entry] = true;
+
+        let offset = UTOC_PARTS_TABLE + entry * 8;
+        areas.push((address(offset), address(offset + 4)));
+        entry = sector[offset + 7] as usize;
+    }
+
+    Ok(areas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pack a disc position into a 24 bit UTOC address
+    fn address(cluster: u32, sector: u32, group: u32) -> u32 {
+        (cluster << 10) | (sector << 4) | group
+    }
+
+    /// Sector 0 of a UTOC with only its sync bytes and the given part
+    /// entries, as `(entry, start, end, link)`
+    fn utoc_sector_0(free_area_pointer: u8, parts: &[(u8, u32, u32, u8)]) -> Vec<u8> {
+        let mut sector = vec![0; UTOC_SECTOR_SIZE];
+        sector[1..11].fill(0xff);
+        sector[UTOC_FREE_AREA_POINTER] = free_area_pointer;
+
+        for &(entry, start, end, link) in parts {
+            let offset = UTOC_PARTS_TABLE + entry as usize * 8;
+            sector[offset..offset + 3].copy_from_slice(&start.to_be_bytes()[1..]);
+            sector[offset + 4..offset + 7].copy_from_slice(&end.to_be_bytes()[1..]);
+            sector[offset + 7] = link;
+        }
+
+        sector
+    }
+
+    #[test]
+    fn free_areas_follow_the_chain() {
+        let first = (address(0x32, 0, 0), address(0x40, 0x1f, 0xa));
+        let second = (address(0x100, 0, 0), address(0x8c0, 0x1f, 0xa));
+
+        // Entry 2 is a recorded part, which isn't linked from P-FRA
+        let sector = utoc_sector_0(
+            3,
+            &[
+                (1, second.0, second.1, 0),
+                (2, address(0x41, 0, 0), address(0xff, 0x1f, 0xa), 0),
+                (3, first.0, first.1, 1),
+            ],
+        );
+
+        assert_eq!(utoc_free_areas(&sector).unwrap(), vec![first, second]);
+    }
+
+    #[test]
+    fn no_free_areas() {
+        let sector = utoc_sector_0(0, &[(1, 1, 2, 0)]);
+
+        assert!(utoc_free_areas(&sector).unwrap().is_empty());
+    }
+
+    #[test]
+    fn looping_chain_stops() {
+        let sector = utoc_sector_0(1, &[(1, 10, 20, 2), (2, 30, 40, 1)]);
+        assert_eq!(utoc_free_areas(&sector).unwrap(), vec![(10, 20), (30, 40)]);
+
+        let sector = utoc_sector_0(5, &[(5, 10, 20, 5)]);
+        assert_eq!(utoc_free_areas(&sector).unwrap(), vec![(10, 20)]);
+    }
+
+    #[test]
+    fn last_part_entry_ends_the_sector() {
+        let sector = utoc_sector_0(255, &[(255, 10, 20, 0)]);
+
+        assert_eq!(UTOC_PARTS_TABLE + 256 * 8, UTOC_SECTOR_SIZE);
+        assert_eq!(utoc_free_areas(&sector).unwrap(), vec![(10, 20)]);
+    }
+
+    #[test]
+    fn sector_of_wrong_length() {
+        for length in [0, UTOC_FREE_AREA_POINTER, UTOC_SECTOR_SIZE - 1, 2048] {
+            assert!(matches!(
+                utoc_free_areas(&vec![0; length]),
+                Err(InterfaceError::InvalidUtocLength(l)) if l == length
+            ));
+        }
+    }
+}
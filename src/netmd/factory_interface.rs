@@ -0,0 +1,389 @@
+use std::time::Duration;
+
+#[cfg(feature = "factory-writes")]
+use crate::netmd::factory_commands::get_descriptive_device_code;
+use crate::netmd::interface::{InterfaceError, NetMDInterface};
+#[cfg(feature = "factory-writes")]
+use crate::netmd::patches::{Patch, PatchName, PatchRegistry};
+use crate::netmd::query_utils::{format_query, scan_query, QueryValue};
+#[cfg(feature = "factory-writes")]
+use crate::netmd::utils::calculate_checksum;
+use crate::netmd::utils::{cross_sleep, to_sjis};
+
+/// The kinds of memory which can be accessed through the factory interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryType {
+    Mapped = 0x00,
+    Eeprom2 = 0x02,
+    Eeprom3 = 0x03,
+}
+
+/// The device code of a NetMD device, from [`NetMDFactoryInterface::get_device_code`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceCode {
+    pub chip_type: u8,
+    pub hwid: u8,
+    /// The firmware version, with the major version in the upper nibble
+    pub version: u8,
+    pub subversion: u8,
+}
+
+/// Get the device code from the reply to a device code query
+fn scan_device_code_reply(reply: Vec<u8>) -> Result<DeviceCode, InterfaceError> {
+    let res = scan_query(reply, "1812 00 %b %b %b %b".to_string())?;
+
+    // The subversion comes before the version in the reply
+    Ok(DeviceCode {
+        chip_type: res[0].to_i64().unwrap() as u8,
+        hwid: res[1].to_i64().unwrap() as u8,
+        subversion: res[2].to_i64().unwrap() as u8,
+        version: res[3].to_i64().unwrap() as u8,
+    })
+}
+
+/// Get the data from the reply to a peripheral metadata read, which echoes
+/// the sector, offset and length of the request before the data
+fn scan_metadata_peripheral_reply(reply: Vec<u8>) -> Result<Vec<u8>, InterfaceError> {
+    let res = scan_query(reply, "1824 00 %?%? %?%? %? 00 %*".to_string())?;
+
+    Ok(res[0].to_vec().unwrap())
+}
+
+/// Get the data from the reply to a memory read.
+///
+/// The data is followed by a 2 byte checksum. Whether it is calculated
+/// like the one sent when writing isn't known, so it is not checked.
+fn scan_memory_read_reply(reply: Vec<u8>) -> Result<Vec<u8>, InterfaceError> {
+    let res = scan_query(reply, "1821 00 %? %?%?%?%? %? %?%? %*".to_string())?;
+
+    let mut data = res[0].to_vec().unwrap();
+    if data.len() < 2 {
+        return Err(InterfaceError::TruncatedResponse);
+    }
+    data.truncate(data.len() - 2);
+
+    Ok(data)
+}
+
+/// An interface to the factory mode of a NetMD device
+///
+/// This is created by [`NetMDInterface::factory`], which also
+/// authenticates it.
+pub struct NetMDFactoryInterface<'a> {
+    interface: &'a mut NetMDInterface,
+}
+
+impl<'a> NetMDFactoryInterface<'a> {
+    const MAX_FACTORY_MODE_ATTEMPTS: u32 = 5;
+    const FACTORY_MODE_RETRY_INTERVAL: u64 = 100;
+    /// The number of Shift-JIS bytes which fit on the display
+    pub const DISPLAY_WIDTH: usize = 10;
+
+    pub(super) fn new(interface: &'a mut NetMDInterface) -> Self {
+        Self { interface }
+    }
+
+    async fn send_query(&mut self, query: &mut Vec<u8>) -> Result<Vec<u8>, InterfaceError> {
+        self.interface.send_factory_query(query, false, false).await
+    }
+
+    /// Authenticate with the factory interface by sending the "Net MD Walkman" magic
+    pub async fn auth(&mut self) -> Result<(), InterfaceError> {
+        let mut query = format_query("1801 ff0e 4e6574204d442057616c6b6d616e".to_string(), vec![])?;
+
+        let reply = self.send_query(&mut query).await?;
+        scan_query(reply, "1801 000e 4e6574204d442057616c6b6d616e".to_string())?;
+
+        Ok(())
+    }
+
+    /// Perform the full factory mode entry sequence.
+    ///
+    /// Most devices accept factory commands as soon as [`Self::auth`] has
+    /// succeeded, but some take a moment to switch modes and reject
+    /// commands in the meantime. This authenticates, then polls the device
+    /// until it answers a factory command. Devices which never accept
+    /// factory commands return [`InterfaceError::NotImplemented`].
+    ///
+    /// Which device codes need the extra wait isn't known, so rather than
+    /// keeping a list of them every device is polled. A device which is
+    /// already in factory mode answers the first poll, so this costs a
+    /// single extra query on those.
+    pub async fn enter_factory_mode(&mut self) -> Result<(), InterfaceError> {
+        match self.auth().await {
+            Ok(_) => (),
+            Err(InterfaceError::Rejected(reply)) => {
+                return Err(InterfaceError::NotImplemented(reply))
+            }
+            Err(error) => return Err(error),
+        }
+
+        let mut attempt = 1;
+        loop {
+            match self.get_device_code().await {
+                Ok(_) => return Ok(()),
+                Err(InterfaceError::Rejected(reply) | InterfaceError::NotImplemented(reply)) => {
+                    if attempt == Self::MAX_FACTORY_MODE_ATTEMPTS {
+                        return Err(InterfaceError::NotImplemented(reply));
+                    }
+                }
+                Err(error) => return Err(error),
+            }
+
+            cross_sleep(Duration::from_millis(
+                Self::FACTORY_MODE_RETRY_INTERVAL * attempt as u64,
+            ))
+            .await;
+            attempt += 1;
+        }
+    }
+
+    /// Get the device code, which identifies the chip and firmware
+    pub async fn get_device_code(&mut self) -> Result<DeviceCode, InterfaceError> {
+        let mut query = format_query("1812 ff".to_string(), vec![])?;
+
+        let reply = self.send_query(&mut query).await?;
+        scan_device_code_reply(reply)
+    }
+
+    /// Read `length` bytes of memory starting at `address`
+    pub async fn read(
+        &mut self,
+        address: u32,
+        length: u8,
+        memory_type: MemoryType,
+    ) -> Result<Vec<u8>, InterfaceError> {
+        let mut query = format_query(
+            "1821 ff %b %<d %b".to_string(),
+            vec![
+                QueryValue::Number(memory_type as i64),
+                QueryValue::Number(address as i64),
+                QueryValue::Number(length as i64),
+            ],
+        )?;
+
+        let reply = self.send_query(&mut query).await?;
+        scan_memory_read_reply(reply)
+    }
+
+    /// Write `data` to memory starting at `address`
+    #[cfg(feature = "factory-writes")]
+    pub async fn write(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        memory_type: MemoryType,
+    ) -> Result<(), InterfaceError> {
+        let mut query = format_query(
+            "1822 ff %b %<d %b 0000 %* %<w".to_string(),
+            vec![
+                QueryValue::Number(memory_type as i64),
+                QueryValue::Number(address as i64),
+                QueryValue::Number(data.len() as i64),
+                QueryValue::Array(data.to_vec()),
+                QueryValue::Number(calculate_checksum(data) as i64),
+            ],
+        )?;
+
+        let reply = self.send_query(&mut query).await?;
+        scan_query(reply, "1822 00 %? %?%?%?%? %? %?%?".to_string())?;
+
+        Ok(())
+    }
+
+    /// Apply the patch registered under `name` for this device.
+    ///
+    /// The memory is checked against the original bytes of the patch
+    /// before writing, so a patch is never applied to the wrong firmware
+    /// or applied twice.
+    #[cfg(feature = "factory-writes")]
+    pub async fn apply_patch(
+        &mut self,
+        registry: &PatchRegistry,
+        name: PatchName,
+    ) -> Result<(), InterfaceError> {
+        let patch = self.find_patch(registry, name).await?;
+
+        self.replace_memory(&patch, &patch.original_bytes, &patch.patched_bytes)
+            .await
+    }
+
+    /// Revert a patch applied with [`Self::apply_patch`], restoring the
+    /// original bytes
+    #[cfg(feature = "factory-writes")]
+    pub async fn revert_patch(
+        &mut self,
+        registry: &PatchRegistry,
+        name: PatchName,
+    ) -> Result<(), InterfaceError> {
+        let patch = self.find_patch(registry, name).await?;
+
+        self.replace_memory(&patch, &patch.patched_bytes, &patch.original_bytes)
+            .await
+    }
+
+    #[cfg(feature = "factory-writes")]
+    async fn find_patch(
+        &mut self,
+        registry: &PatchRegistry,
+        name: PatchName,
+    ) -> Result<Patch, InterfaceError> {
+        let device_code = get_descriptive_device_code(self).await?;
+
+        registry
+            .get(&device_code, name)
+            .cloned()
+            .ok_or(InterfaceError::UnknownPatch {
+                device_code,
+                name: name.to_string(),
+            })
+    }
+
+    /// Write `new` over the memory of a patch, if it currently holds `expected`
+    #[cfg(feature = "factory-writes")]
+    async fn replace_memory(
+        &mut self,
+        patch: &Patch,
+        expected: &[u8],
+        new: &[u8],
+    ) -> Result<(), InterfaceError> {
+        let address = patch.address();
+
+        let current = self
+            .read(address, expected.len() as u8, MemoryType::Mapped)
+            .await?;
+        if current != expected {
+            return Err(InterfaceError::PatchMismatch { address });
+        }
+
+        self.write(address, new, MemoryType::Mapped).await
+    }
+
+    /// Show `text` on the display of the device instead of its usual contents.
+    ///
+    /// The text is encoded as Shift-JIS, where full width characters take
+    /// up two bytes, and must fit in [`Self::DISPLAY_WIDTH`] bytes.
+    pub async fn set_display_override_string(
+        &mut self,
+        text: &str,
+        blink: bool,
+    ) -> Result<(), InterfaceError> {
+        let mut text = to_sjis(text);
+        if text.len() > Self::DISPLAY_WIDTH {
+            return Err(InterfaceError::TitleTooLong {
+                length: text.len(),
+                max: Self::DISPLAY_WIDTH,
+            });
+        }
+        text.resize(Self::DISPLAY_WIDTH, 0);
+
+        let mut query = format_query(
+            "1852 ff %b 00 %*".to_string(),
+            vec![QueryValue::Number(blink as i64), QueryValue::Array(text)],
+        )?;
+
+        let reply = self.send_query(&mut query).await?;
+        scan_query(reply, "1852 00 %? 00 %*".to_string())?;
+
+        Ok(())
+    }
+
+    /// Read `length` bytes of peripheral metadata from `sector` at `offset`
+    pub async fn read_metadata_peripheral(
+        &mut self,
+        sector: u16,
+        offset: u16,
+        length: u8,
+    ) -> Result<Vec<u8>, InterfaceError> {
+        let mut query = format_query(
+            "1824 ff %w %w %b 00".to_string(),
+            vec![
+                QueryValue::Number(sector as i64),
+                QueryValue::Number(offset as i64),
+                QueryValue::Number(length as i64),
+            ],
+        )?;
+
+        let reply = self.send_query(&mut query).await?;
+
+        scan_metadata_peripheral_reply(reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_peripheral_reply() {
+        // A read of 4 bytes from sector 0x0010 at offset 0x0002
+        let reply = vec![
+            0x09, 0x18, 0x24, 0x00, 0x00, 0x10, 0x00, 0x02, 0x04, 0x00, 0xde, 0xad, 0xbe, 0xef,
+        ];
+
+        assert_eq!(
+            scan_metadata_peripheral_reply(reply).unwrap(),
+            vec![0xde, 0xad, 0xbe, 0xef]
+        );
+    }
+
+    #[test]
+    fn metadata_peripheral_reply_wrong_command() {
+        // The reply to a memory read, which the old format expected
+        let reply = vec![
+            0x09, 0x18, 0x21, 0x00, 0x00, 0x10, 0x00, 0x02, 0x04, 0x00, 0xde, 0xad, 0xbe, 0xef,
+        ];
+
+        assert!(scan_metadata_peripheral_reply(reply).is_err());
+    }
+
+    #[test]
+    fn memory_read_reply() {
+        // A read of 4 bytes of mapped memory from 0x0000f000
+        let reply = vec![
+            0x09, 0x18, 0x21, 0x00, 0x00, 0x00, 0xf0, 0x00, 0x00, 0x04, 0x00, 0x00, 0xde, 0xad,
+            0xbe, 0xef, 0x12, 0x34,
+        ];
+        assert_eq!(
+            scan_memory_read_reply(reply).unwrap(),
+            vec![0xde, 0xad, 0xbe, 0xef]
+        );
+
+        // The checksum isn't verified, so a different one is still accepted
+        let reply = vec![
+            0x09, 0x18, 0x21, 0x00, 0x00, 0x00, 0xf0, 0x00, 0x00, 0x04, 0x00, 0x00, 0xde, 0xad,
+            0xbe, 0xef, 0x00, 0x00,
+        ];
+        assert_eq!(
+            scan_memory_read_reply(reply).unwrap(),
+            vec![0xde, 0xad, 0xbe, 0xef]
+        );
+    }
+
+    #[test]
+    fn memory_read_reply_without_checksum() {
+        let reply = vec![
+            0x09, 0x18, 0x21, 0x00, 0x00, 0x00, 0xf0, 0x00, 0x00, 0x01, 0x00, 0x00, 0xde,
+        ];
+        assert!(matches!(
+            scan_memory_read_reply(reply),
+            Err(InterfaceError::TruncatedResponse)
+        ));
+    }
+
+    #[test]
+    fn device_code_reply() {
+        // An R type chip with firmware 1.200
+        let reply = vec![0x09, 0x18, 0x12, 0x00, 0x20, 0x01, 0x00, 0x12];
+
+        assert_eq!(
+            scan_device_code_reply(reply).unwrap(),
+            DeviceCode {
+                chip_type: 0x20,
+                hwid: 0x01,
+                version: 0x12,
+                subversion: 0x00,
+            }
+        );
+    }
+}
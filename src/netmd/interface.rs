@@ -1,9 +1,9 @@
 #![cfg_attr(debug_assertions, allow(dead_code))]
 use crate::netmd::base;
-use crate::netmd::query_utils::{format_query, scan_query, QueryValue};
+use crate::netmd::codec;
+use crate::netmd::query_utils::{format_query, scan_query, scan_query_lenient, QueryValue};
 use crate::netmd::utils::{
-    half_width_to_full_width_range, length_after_encoding_to_sjis, sanitize_full_width_title,
-    sanitize_half_width_title, RawTime,
+    half_width_to_full_width_range, sanitize_full_width_title, sanitize_half_width_title, RawTime,
 };
 use cbc::cipher::block_padding::NoPadding;
 use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyInit, KeyIvInit};
@@ -11,14 +11,23 @@ use encoding_rs::SHIFT_JIS;
 use num_derive::FromPrimitive;
 use rand::RngCore;
 use std::collections::HashMap;
-use std::error::Error;
 use std::time::Duration;
 use thiserror::Error;
 use tokio::sync::mpsc::UnboundedReceiver;
 
 use super::base::NetMD;
+use super::encryption::new_thread_encryptor;
+use super::factory_commands::{read_utoc_sector, utoc_free_areas, UTOC_SECTOR_COUNT};
+use super::factory_interface::NetMDFactoryInterface;
 use super::utils::{cross_sleep, to_sjis};
 
+/// The most characters a title read from the TOC can contain, used to
+/// reject bogus lengths reported by the device
+const MAX_TITLE_LENGTH: i32 = 1700;
+
+/// The number of title cells in the UTOC, each of which holds 7 characters
+pub const TITLE_CELL_LIMIT: usize = 255;
+
 #[derive(Copy, Clone)]
 enum Action {
     Play = 0x75,
@@ -50,6 +59,26 @@ pub enum WireFormat {
 }
 
 impl WireFormat {
+    /// The size of a single frame of this format in bytes.
+    ///
+    /// A frame is always 512 samples (per channel) long, and every wire
+    /// format is stereo, so the size only depends on the bitrate:
+    ///
+    /// | Format     | Bitrate   | Frame size |
+    /// |------------|-----------|------------|
+    /// | `Pcm`      | 1411 kbps | 2048       |
+    /// | `LP2`      | 132 kbps  | 192        |
+    /// | `L105kbps` | 105 kbps  | 152        |
+    /// | `LP4`      | 66 kbps   | 96         |
+    ///
+    /// The channel count of the recorded track doesn't change the size, as
+    /// the data is always sent in stereo. A mono SP track is sent as stereo
+    /// PCM and recorded as [`DiscFormat::SPMono`], so the frame count of a
+    /// track only depends on its duration.
+    ///
+    /// For [`WireFormat::Pcm`] a frame is 16 bit big-endian stereo audio,
+    /// the same duration as an SP sound group. ATRAC3 frames are 1024
+    /// samples long, so each one is sent as two of these frames.
     const fn frame_size(&self) -> u16 {
         match self {
             WireFormat::Pcm => 2048,
@@ -59,17 +88,19 @@ impl WireFormat {
         }
     }
 
+    /// The number of frames of this format which fit in a duration
+    pub fn frames_in(&self, duration: RawTime) -> usize {
+        // `as_frames` counts 1/512ths of a second, while a frame of any
+        // format is 512 samples at 44.1kHz
+        (duration.as_frames() * 44100 / (512 * 512)) as usize
+    }
+
     const fn disc_for_wire(&self) -> DiscFormat {
-        match self {
-            WireFormat::Pcm => DiscFormat::SPStereo,
-            WireFormat::L105kbps => DiscFormat::LP2,
-            WireFormat::LP2 => DiscFormat::LP2,
-            WireFormat::LP4 => DiscFormat::LP4,
-        }
+        codec::disc_format_for_wire(self)
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Encoding {
     SP = 0x90,
     LP2 = 0x92,
@@ -86,6 +117,20 @@ impl std::fmt::Display for Encoding {
     }
 }
 
+impl From<Encoding> for u8 {
+    fn from(encoding: Encoding) -> u8 {
+        codec::byte_from_encoding(encoding)
+    }
+}
+
+impl std::convert::TryFrom<u8> for Encoding {
+    type Error = InterfaceError;
+
+    fn try_from(item: u8) -> Result<Self, InterfaceError> {
+        codec::encoding_from_byte(item)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Channels {
     Mono = 0x01,
@@ -101,17 +146,110 @@ impl std::fmt::Display for Channels {
     }
 }
 
+impl std::convert::TryFrom<u8> for Channels {
+    type Error = InterfaceError;
+
+    fn try_from(item: u8) -> Result<Self, InterfaceError> {
+        match item {
+            0x00 => Ok(Channels::Stereo),
+            0x01 => Ok(Channels::Mono),
+            _ => Err(InterfaceError::InvalidDiscFormat(item)),
+        }
+    }
+}
+
+/// The recording mode the device will use for its own inputs
+#[derive(Debug, Clone, Copy)]
+pub struct RecordingParameters {
+    pub encoding: Encoding,
+    pub channels: Channels,
+}
+
 enum ChannelCount {
     Mono = 1,
     Stereo = 2,
 }
 
+/// The current playback position as reported by the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    /// The track being played, starting from 0
+    pub track: u16,
+    pub hour: u16,
+    pub minute: u16,
+    pub second: u16,
+    /// The frame within the current second, there are 512 per second
+    pub frame: u16,
+}
+
+impl Position {
+    /// The position within the current track as a [`RawTime`]
+    pub fn as_raw_time(&self) -> RawTime {
+        RawTime {
+            hours: self.hour as u64,
+            minutes: self.minute as u64,
+            seconds: self.second as u64,
+            frames: self.frame as u64,
+        }
+    }
+
+    /// The position within the current track as a [`Duration`]
+    pub fn as_duration(&self) -> Duration {
+        self.as_raw_time().as_duration()
+    }
+}
+
+/// A playback status block, as returned by [`NetMDInterface::playback_status1`]
+/// and [`NetMDInterface::playback_status2`].
+///
+/// Only part of the block read by [`NetMDInterface::playback_status2`] is
+/// understood:
+///
+/// | Bytes | Contents                                                       |
+/// |-------|----------------------------------------------------------------|
+/// | 0..4  | Not decoded                                                    |
+/// | 4..6  | The operating state as a big-endian number, see [`Self::state`] |
+/// | 6..   | Not decoded                                                    |
+///
+/// The current track is read with [`NetMDInterface::position`] instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaybackStatus {
+    raw: Vec<u8>,
+}
+
+impl PlaybackStatus {
+    /// The operating state of the device, if the block is long enough
+    /// to contain it. The values are listed in
+    /// [`OperatingStatus`](super::commands::OperatingStatus).
+    pub fn state(&self) -> Option<u16> {
+        self.raw
+            .get(4..6)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// The undecoded bytes of the block
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+}
+
 #[derive(Debug, Clone, Copy, FromPrimitive)]
 pub enum TrackFlag {
     Protected = 0x03,
     Unprotected = 0x00,
 }
 
+impl TrackFlag {
+    /// Get the protection state from the flags byte of a track, ignoring
+    /// any other bits which are set
+    pub fn from_flags(flags: u8) -> Self {
+        match flags & TrackFlag::Protected as u8 {
+            0 => TrackFlag::Unprotected,
+            _ => TrackFlag::Protected,
+        }
+    }
+}
+
 impl std::fmt::Display for TrackFlag {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -121,6 +259,7 @@ impl std::fmt::Display for TrackFlag {
     }
 }
 
+/// Flags reported by [`NetMDInterface::disc_flags`].
 pub enum DiscFlag {
     Writable = 0x10,
     WriteProtected = 0x40,
@@ -241,6 +380,81 @@ pub enum EncryptionError {
     InvalidLength(&'static str, usize),
 }
 
+/// The 8 byte leaf ID of a device, see [`NetMDInterface::leaf_id`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LeafId([u8; 8]);
+
+impl LeafId {
+    pub fn as_bytes(&self) -> &[u8; 8] {
+        &self.0
+    }
+}
+
+impl From<[u8; 8]> for LeafId {
+    fn from(value: [u8; 8]) -> Self {
+        Self(value)
+    }
+}
+
+impl TryFrom<&[u8]> for LeafId {
+    type Error = EncryptionError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self(value.try_into().map_err(|_| {
+            EncryptionError::InvalidLength("leaf ID", value.len())
+        })?))
+    }
+}
+
+/// The 8 byte DES key used to encrypt data during a secure session
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SessionKey([u8; 8]);
+
+impl SessionKey {
+    pub fn as_bytes(&self) -> &[u8; 8] {
+        &self.0
+    }
+}
+
+impl From<[u8; 8]> for SessionKey {
+    fn from(value: [u8; 8]) -> Self {
+        Self(value)
+    }
+}
+
+impl TryFrom<&[u8]> for SessionKey {
+    type Error = EncryptionError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self(value.try_into().map_err(|_| {
+            EncryptionError::InvalidLength("session key", value.len())
+        })?))
+    }
+}
+
+impl std::fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the key into logs
+        f.write_str("SessionKey(..)")
+    }
+}
+
+/// A reply to a command which the device did not accept
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord)]
+pub struct CommandReply {
+    /// The first two bytes of the command which was sent, such as `0x1843`
+    /// for [`NetMDInterface::move_track`]
+    pub opcode: u16,
+    /// The full reply from the device
+    pub reply: Vec<u8>,
+}
+
+impl std::fmt::Display for CommandReply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "command {:04x}: {:02X?}", self.opcode, self.reply)
+    }
+}
+
 #[derive(Error, Debug, Eq, PartialEq, PartialOrd, Ord)]
 pub enum InterfaceError {
     #[error("could not parse data from a device")]
@@ -249,7 +463,7 @@ pub enum InterfaceError {
     #[error("communication with the device failed")]
     CommunicationError(#[from] crate::netmd::base::NetMDError),
 
-    #[error("track {0} is in 2 groups")]
+    #[error("invalid group: {0}")]
     GroupError(String),
 
     #[error("encryption error: {0:?}")]
@@ -264,52 +478,340 @@ pub enum InterfaceError {
     #[error("invalid NetMD level: {0}")]
     InvalidLevel(u8),
 
-    #[error("track encoding value {0} out of range (0x90..0x92)")]
+    #[error("track encoding value {0:#04x} is not one of 0x90, 0x92 or 0x93")]
     InvalidEncoding(u8),
 
     #[error("disc format value {0} out of range (0..6)")]
     InvalidDiscFormat(u8),
 
-    #[error("the device rejected the message")]
-    Rejected(String),
+    #[error("the device rejected {0}")]
+    Rejected(CommandReply),
 
     #[error("the title is the same as the target title")]
     TitleError,
 
-    #[error("the device replied that the command was not recognized")]
-    NotImplemented(String),
+    #[error("the device did not recognize {0}")]
+    NotImplemented(CommandReply),
 
     #[error("the maximum number of retry attempts was reached")]
     MaxRetries,
 
-    #[error("the device replied with an unknown status")]
-    Unknown(String),
+    #[error("the device replied with an unknown status to {0}")]
+    Unknown(CommandReply),
+
+    #[error("the response from the device was shorter than expected")]
+    TruncatedResponse,
+
+    #[error("downloaded track has {actual} frames, expected {expected}")]
+    DownloadVerificationFailed { expected: usize, actual: usize },
+
+    #[error("track {requested} does not exist, the disc has {count} tracks")]
+    InvalidTrack { requested: u16, count: u16 },
+
+    #[error("UTOC data has an invalid length of {0} bytes")]
+    InvalidUtocLength(usize),
+
+    #[error("the device did not reach the expected state in time")]
+    Timeout,
+
+    #[error("the track has no audio data")]
+    EmptyTrack,
+
+    #[error("no patch named {name} for device {device_code}")]
+    UnknownPatch { device_code: String, name: String },
+
+    #[error("the memory at {address:#010x} does not match the patch")]
+    PatchMismatch { address: u32 },
+
+    #[error("the text is {length} bytes long, but at most {max} bytes fit")]
+    TitleTooLong { length: usize, max: usize },
+
+    #[error("the secure session has not been started")]
+    SessionNotStarted,
+
+    #[error("the device can not eject discs")]
+    EjectNotSupported,
+
+    #[error("the device can not upload tracks")]
+    UploadNotSupported,
+
+    #[error("title is not valid Shift-JIS: {0:02X?}")]
+    InvalidTitleEncoding(Vec<u8>),
+
+    #[error("track {0} is protected")]
+    TrackProtected(u16),
+
+    #[error("the disc is write protected")]
+    WriteProtected,
+
+    #[error("the disc is not recordable")]
+    DiscNotWritable,
+
+    #[error("the disc was not empty after formatting, {track_count} tracks remain")]
+    FormatFailed { track_count: u16 },
+
+    #[error("the new track was added, but the old track is still on the disc as track {index}")]
+    ReplaceIncomplete {
+        index: u16,
+        source: Box<InterfaceError>,
+    },
+}
+
+/// Parse a reply to a disc title read, returning the number of title bytes
+/// in it, the total length of the title, which is only sent in the first
+/// reply, and the title bytes themselves
+fn scan_disc_title_chunk(
+    reply: Vec<u8>,
+    first: bool,
+) -> Result<(i32, Option<i32>, Vec<u8>), InterfaceError> {
+    if !first {
+        let res = scan_query(
+            reply,
+            "1806 02201801 00%? 3000 0a00 1000 %w%?%? %*".to_string(),
+        )?;
+
+        return Ok((
+            res[0].to_i64().unwrap() as u16 as i32,
+            None,
+            res[1].to_vec().unwrap(),
+        ));
+    }
+
+    let res = scan_query(
+        reply,
+        "1806 02201801 00%? 3000 0a00 1000 %w0000 %?%?000a %w %*".to_string(),
+    )?;
+
+    // The lengths are unsigned, a bogus length must not wrap around
+    let chunksize = res[0].to_i64().unwrap() as u16 as i32;
+    let total = res[1].to_i64().unwrap() as u16 as i32;
+    if total > MAX_TITLE_LENGTH {
+        return Err(InterfaceError::TruncatedResponse);
+    }
+
+    // The size of the first chunk includes the header before the title
+    Ok((chunksize - 6, Some(total), res[2].to_vec().unwrap()))
+}
+
+/// Read an `n` byte big-endian number from `buffer` at `offset`, advancing
+/// `offset` past it
+fn construct_multibyte(buffer: &[u8], n: u8, offset: &mut usize) -> Result<u32, InterfaceError> {
+    let mut output: u32 = 0;
+    for _ in 0..n as usize {
+        output <<= 8;
+        output |= *buffer
+            .get(*offset)
+            .ok_or(InterfaceError::TruncatedResponse)? as u32;
+        *offset += 1;
+    }
+    Ok(output)
+}
+
+/// Parse the groups stored in the raw disc titles, see
+/// [`NetMDInterface::track_group_list`].
+///
+/// With `repair`, problems are skipped like in
+/// [`NetMDInterface::repaired_track_group_list`].
+#[allow(clippy::type_complexity)]
+pub(crate) fn parse_track_groups(
+    raw_title: &str,
+    raw_full_title: &str,
+    track_count: u16,
+    repair: bool,
+) -> Result<Vec<(Option<String>, Option<String>, Vec<u16>)>, InterfaceError> {
+    let group_list = raw_title.split("//");
+    let mut track_dict: HashMap<u16, (String, u16)> = HashMap::new();
+    let mut result: Vec<(Option<String>, Option<String>, Vec<u16>)> = Vec::new();
+
+    let mut full_width_group_list = raw_full_title.split("／／");
+
+    for (i, group) in group_list.enumerate() {
+        if group.is_empty() {
+            continue;
+        }
+
+        if group.starts_with("0;") || group.find(';').is_none() || !raw_title.contains("//") {
+            continue;
+        }
+
+        let track_range: String = match group.split_once(';') {
+            Some(string) => string.0.to_string(),
+            None => return Err(InterfaceError::GroupError("no groups were found".into())),
+        };
+        if track_range.is_empty() {
+            continue;
+        }
+
+        let group_name = &group[track_range.len() + 1..];
+
+        let full_width_range = half_width_to_full_width_range(&track_range);
+
+        let full_width_group_name = full_width_group_list
+            .find(|n| n.starts_with(&full_width_range))
+            .and_then(|n| n.split_once('；'))
+            .map(|n| n.1.to_string());
+
+        let malformed_range =
+            || InterfaceError::GroupError(format!("malformed group range \"{}\"", track_range));
+
+        let (track_min, track_max) = match track_range.split_once('-') {
+            Some((min, max)) => (min.parse::<u16>(), max.parse::<u16>()),
+            None => (track_range.parse::<u16>(), track_range.parse::<u16>()),
+        };
+        let (Ok(track_min), Ok(track_max)) = (track_min, track_max) else {
+            if repair {
+                continue;
+            }
+            return Err(malformed_range());
+        };
+
+        let track_max = u16::min(track_max, track_count);
+
+        if track_min == 0 || track_min > track_max {
+            if repair {
+                continue;
+            }
+            return Err(malformed_range());
+        }
+
+        let mut track_list: Vec<u16> = Vec::new();
+        for track in track_min - 1..track_max {
+            if track_dict.contains_key(&track) {
+                if repair {
+                    continue;
+                }
+                return Err(InterfaceError::GroupError(format!(
+                    "track {} is in 2 groups",
+                    track
+                )));
+            }
+            track_dict.insert(track, (String::from(group_name), i as u16));
+            track_list.push(track);
+        }
+
+        // Tracks already in another group were skipped, which can leave
+        // gaps, but a group has to be a single range of tracks. The
+        // remainder is split into a group for each run of tracks.
+        for run in track_list.chunk_by(|a, b| a + 1 == *b) {
+            result.push((
+                Some(String::from(group_name)),
+                full_width_group_name.clone(),
+                run.to_vec(),
+            ));
+        }
+    }
+
+    for i in 0..track_count {
+        if !track_dict.contains_key(&i) {
+            result.insert(0, (None, None, Vec::from([i])))
+        }
+    }
+
+    Ok(result)
+}
+
+/// Get the used, total and available time from the reply to a disc
+/// capacity query
+fn scan_disc_capacity_reply(reply: Vec<u8>) -> Result<[RawTime; 3], InterfaceError> {
+    // Sony devices reply with 8003 where Panasonic devices reply with 0803,
+    // the meaning of these bytes is unknown. Panasonic devices can also
+    // append trailing bytes to the reply.
+    let format = |marker| {
+        format!("1806 02101000 3080 0300 1000 001d0000 001b {marker} 0017 8000 0005 %W %B %B %B 0005 %W %B %B %B 0005 %W %B %B %B")
+    };
+    let res = scan_query_lenient(reply.clone(), format("8003"))
+        .or_else(|_| scan_query_lenient(reply, format("0803")))?;
+
+    let res_num: Vec<RawTime> = res
+        .chunks(4)
+        .map(|t| RawTime {
+            hours: t[0].to_i64().unwrap() as u64,
+            minutes: t[1].to_i64().unwrap() as u64,
+            seconds: t[2].to_i64().unwrap() as u64,
+            frames: t[3].to_i64().unwrap() as u64,
+        })
+        .collect();
+
+    Ok(res_num.try_into().unwrap())
 }
 
 /// An interface for interacting with a NetMD device
 pub struct NetMDInterface {
     pub device: NetMD,
+    /// The opcode of the last command sent, for errors
+    last_opcode: u16,
+    max_interim_read_attempts: u8,
+    interim_retry_interval: Duration,
+}
+
+/// Decode a title as stored on the disc.
+///
+/// Invalid bytes are replaced, so one corrupt title doesn't stop the rest
+/// of the disc from being read. The second value is `true` if any bytes
+/// were replaced.
+pub(crate) fn decode_title(bytes: &[u8]) -> (String, bool) {
+    let (title, _, had_errors) = SHIFT_JIS.decode(bytes);
+
+    (title.into_owned(), had_errors)
 }
 
 #[allow(dead_code)]
 impl NetMDInterface {
-    const MAX_INTERIM_READ_ATTEMPTS: u8 = 4;
-    const INTERIM_RESPONSE_RETRY_INTERVAL: u32 = 100;
+    const DEFAULT_MAX_INTERIM_READ_ATTEMPTS: u8 = 4;
+    const DEFAULT_INTERIM_RETRY_INTERVAL: Duration = Duration::from_millis(100);
 
     /// Get a new interface to a NetMD device
     pub async fn new(device: cross_usb::Descriptor) -> Result<Self, InterfaceError> {
         let device = base::NetMD::new(device).await?;
-        Ok(NetMDInterface { device })
+        Ok(Self::from_netmd(device))
     }
 
-    fn construct_multibyte(&mut self, buffer: &[u8], n: u8, offset: &mut usize) -> u32 {
-        let mut output: u32 = 0;
-        for _ in 0..n as usize {
-            output <<= 8;
-            output |= buffer[*offset] as u32;
-            *offset += 1;
+    /// Get a new interface to a NetMD device from an existing connection,
+    /// see [`base::NetMD::from_interface`]
+    pub fn from_netmd(device: base::NetMD) -> Self {
+        NetMDInterface {
+            device,
+            last_opcode: 0,
+            max_interim_read_attempts: Self::DEFAULT_MAX_INTERIM_READ_ATTEMPTS,
+            interim_retry_interval: Self::DEFAULT_INTERIM_RETRY_INTERVAL,
         }
-        output
+    }
+
+    /// Gets how many times a reply is read while the device is still busy
+    /// with a command
+    pub fn max_interim_read_attempts(&self) -> u8 {
+        self.max_interim_read_attempts
+    }
+
+    /// Sets how many times a reply is read while the device is still busy
+    /// with a command. Slow devices may need more attempts.
+    pub fn set_max_interim_read_attempts(&mut self, attempts: u8) {
+        self.max_interim_read_attempts = attempts
+    }
+
+    /// Gets the base interval between reads of a reply while the device is
+    /// still busy with a command
+    pub fn interim_retry_interval(&self) -> Duration {
+        self.interim_retry_interval
+    }
+
+    /// Sets the base interval between reads of a reply while the device is
+    /// still busy with a command. The wait doubles after each attempt.
+    pub fn set_interim_retry_interval(&mut self, interval: Duration) {
+        self.interim_retry_interval = interval
+    }
+
+    /// Get the name of the device, preferring the product string it reports
+    /// over USB and falling back to the name in [`base::DEVICE_IDS`].
+    ///
+    /// Devices which share an ID with a known model can report a distinct
+    /// product string, which helps to tell them apart.
+    pub fn query_device_name(&self) -> Option<String> {
+        self.device
+            .product_string()
+            .filter(|name| !name.trim().is_empty())
+            .or(self.device.device_name())
+            .map(str::to_string)
     }
 
     // TODO: Finish proper implementation
@@ -341,37 +843,31 @@ impl NetMDInterface {
         let mut buffer_offset: usize = 0;
 
         for _ in 0..amt_of_root_object_lists {
-            root_objects.push(self.construct_multibyte(
+            root_objects.push(construct_multibyte(
                 &buffer,
                 size_of_list_id as u8,
                 &mut buffer_offset,
-            ));
+            )?);
         }
 
-        let _subunit_dependent_length = self.construct_multibyte(&buffer, 2, &mut buffer_offset);
-        let _subunit_fields_length = self.construct_multibyte(&buffer, 2, &mut buffer_offset);
-        let _attributes = buffer[buffer_offset];
-        buffer_offset += 1;
-        let _disc_subunit_version = buffer[buffer_offset];
-        buffer_offset += 1;
+        let _subunit_dependent_length = construct_multibyte(&buffer, 2, &mut buffer_offset)?;
+        let _subunit_fields_length = construct_multibyte(&buffer, 2, &mut buffer_offset)?;
+        let _attributes = construct_multibyte(&buffer, 1, &mut buffer_offset)?;
+        let _disc_subunit_version = construct_multibyte(&buffer, 1, &mut buffer_offset)?;
 
         let mut supported_media_type_specifications: Vec<MediaInfo> = Vec::new();
-        let amt_supported_media_types = buffer[buffer_offset];
-        buffer_offset += 1;
+        let amt_supported_media_types = construct_multibyte(&buffer, 1, &mut buffer_offset)?;
         for _ in 0..amt_supported_media_types {
-            let supported_media_type = self.construct_multibyte(&buffer, 2, &mut buffer_offset);
+            let supported_media_type = construct_multibyte(&buffer, 2, &mut buffer_offset)?;
 
-            let implementation_profile_id = buffer[buffer_offset];
-            buffer_offset += 1;
-            let media_type_attributes = buffer[buffer_offset];
-            buffer_offset += 1;
+            let implementation_profile_id =
+                construct_multibyte(&buffer, 1, &mut buffer_offset)? as u8;
+            let media_type_attributes = construct_multibyte(&buffer, 1, &mut buffer_offset)? as u8;
 
-            let _type_dep_length = self.construct_multibyte(&buffer, 2, &mut buffer_offset);
+            let _type_dep_length = construct_multibyte(&buffer, 2, &mut buffer_offset)?;
 
-            let md_audio_version = buffer[buffer_offset];
-            buffer_offset += 1;
-            let supports_md_clip = buffer[buffer_offset];
-            buffer_offset += 1;
+            let md_audio_version = construct_multibyte(&buffer, 1, &mut buffer_offset)? as u8;
+            let supports_md_clip = construct_multibyte(&buffer, 1, &mut buffer_offset)? as u8;
 
             supported_media_type_specifications.push(MediaInfo {
                 supported_media_type,
@@ -382,9 +878,10 @@ impl NetMDInterface {
             })
         }
 
-        let manufacturer_dep_length = self.construct_multibyte(&buffer, 2, &mut buffer_offset);
-        let _manufacturer_dep_data =
-            &buffer[buffer_offset..buffer_offset + manufacturer_dep_length as usize];
+        let manufacturer_dep_length = construct_multibyte(&buffer, 2, &mut buffer_offset)?;
+        let _manufacturer_dep_data = buffer
+            .get(buffer_offset..buffer_offset + manufacturer_dep_length as usize)
+            .ok_or(InterfaceError::TruncatedResponse)?;
 
         self.change_descriptor_state(&Descriptor::DiscSubunitIdentifier, &DescriptorAction::Close)
             .await?;
@@ -399,17 +896,59 @@ impl NetMDInterface {
         Err(InterfaceError::NoSupportedMedia)
     }
 
-    /* TODO: Finish implementation
-    fn factory(&mut self) -> Result<NetMDLevel, Box<dyn Error>> {
-        let device_name = self.net_md_device.device_name().expect("The device has no name");
+    /// Check that the device is still connected and responding.
+    ///
+    /// This only polls the device, so it is cheap enough to call
+    /// periodically to notice when a device is unplugged or goes to sleep.
+    pub async fn ping(&mut self) -> Result<(), InterfaceError> {
+        self.device.poll().await?;
+
+        Ok(())
+    }
+
+    /// Open the factory interface of the device
+    ///
+    /// The factory interface allows for low level access to the device,
+    /// and is authenticated before it is returned.
+    pub async fn factory(&mut self) -> Result<NetMDFactoryInterface<'_>, InterfaceError> {
+        self.disc_subunit_identifier().await?;
+
+        let mut factory_interface = NetMDFactoryInterface::new(self);
+        factory_interface.auth().await?;
+
+        Ok(factory_interface)
+    }
+
+    /// Read the whole UTOC of the disc, for backing it up.
+    ///
+    /// This uses the factory interface, so devices without one return
+    /// [`InterfaceError::NotImplemented`].
+    pub async fn read_utoc(&mut self) -> Result<Vec<u8>, InterfaceError> {
+        let mut factory_interface = self.factory().await?;
+        factory_interface.enter_factory_mode().await?;
+
+        let mut utoc = Vec::new();
+        for sector in 0..UTOC_SECTOR_COUNT {
+            utoc.append(&mut read_utoc_sector(&mut factory_interface, sector).await?);
+        }
+
+        Ok(utoc)
+    }
 
-        let himd = device_name.contains("MZ-RH") || device_name.contains("MZ-NH");
+    /// Get the free areas of the disc which can be recorded to, as
+    /// `(start, end)` UTOC addresses, see [`utoc_free_areas`].
+    ///
+    /// Only the title descriptors of the UTOC are readable through the
+    /// normal interface, so this reads sector 0 of the UTOC using the
+    /// factory interface, like [`NetMDInterface::read_utoc`].
+    pub async fn free_region(&mut self) -> Result<Vec<(u32, u32)>, InterfaceError> {
+        let mut factory_interface = self.factory().await?;
+        factory_interface.enter_factory_mode().await?;
 
-        self.disc_subunit_identifier()?;
+        let sector = read_utoc_sector(&mut factory_interface, 0).await?;
 
-        let constructor =
+        utoc_free_areas(&sector)
     }
-    */
 
     async fn net_md_level(&mut self) -> Result<NetMDLevel, InterfaceError> {
         let result = self.disc_subunit_identifier().await?;
@@ -443,9 +982,31 @@ impl NetMDInterface {
         test: bool,
         accept_interim: bool,
     ) -> Result<Vec<u8>, InterfaceError> {
-        self.send_command(query, test).await?;
+        self.send_query_inner(query, test, accept_interim, false)
+            .await
+    }
+
+    /// Send a query to the factory interface of the NetMD player
+    pub(super) async fn send_factory_query(
+        &mut self,
+        query: &mut Vec<u8>,
+        test: bool,
+        accept_interim: bool,
+    ) -> Result<Vec<u8>, InterfaceError> {
+        self.send_query_inner(query, test, accept_interim, true)
+            .await
+    }
+
+    async fn send_query_inner(
+        &mut self,
+        query: &mut Vec<u8>,
+        test: bool,
+        accept_interim: bool,
+        factory: bool,
+    ) -> Result<Vec<u8>, InterfaceError> {
+        self.send_command(query, test, factory).await?;
 
-        let result = self.read_reply(accept_interim).await?;
+        let result = self.read_reply_inner(accept_interim, factory).await?;
 
         Ok(result)
     }
@@ -454,59 +1015,93 @@ impl NetMDInterface {
         &mut self,
         query: &mut Vec<u8>,
         test: bool,
+        factory: bool,
     ) -> Result<(), InterfaceError> {
         let status_byte = match test {
             true => NetmdStatus::GeneralInquiry,
             false => NetmdStatus::Control,
         };
 
+        self.last_opcode = u16::from_be_bytes([
+            query.first().copied().unwrap_or_default(),
+            query.get(1).copied().unwrap_or_default(),
+        ]);
+
         let mut new_query = Vec::new();
 
         new_query.push(status_byte as u8);
         new_query.append(query);
 
-        self.device.send_command(new_query).await?;
+        match factory {
+            true => self.device.send_factory_command(new_query).await?,
+            false => self.device.send_command(new_query).await?,
+        }
 
         Ok(())
     }
 
+    fn command_reply(&self, reply: Vec<u8>) -> CommandReply {
+        CommandReply {
+            opcode: self.last_opcode,
+            reply,
+        }
+    }
+
     async fn read_reply(&mut self, accept_interim: bool) -> Result<Vec<u8>, InterfaceError> {
+        self.read_reply_inner(accept_interim, false).await
+    }
+
+    async fn read_reply_inner(
+        &mut self,
+        accept_interim: bool,
+        factory: bool,
+    ) -> Result<Vec<u8>, InterfaceError> {
         let mut current_attempt = 0;
         let mut data;
 
-        while current_attempt < Self::MAX_INTERIM_READ_ATTEMPTS {
-            data = self.device.read_reply(None).await?;
+        while current_attempt < self.max_interim_read_attempts {
+            data = match factory {
+                true => self.device.read_factory_reply(None).await?,
+                false => self.device.read_reply(None).await?,
+            };
 
             let status = NetmdStatus::try_from(data[0])?;
 
             match status {
                 NetmdStatus::NotImplemented => {
-                    return Err(InterfaceError::NotImplemented(format!("{:02X?}", data)))
+                    return Err(InterfaceError::NotImplemented(self.command_reply(data)))
                 }
                 NetmdStatus::Rejected => {
-                    return Err(InterfaceError::Rejected(format!("{:02X?}", data)))
+                    return Err(InterfaceError::Rejected(self.command_reply(data)))
                 }
-                NetmdStatus::Interim if !accept_interim => {
-                    let sleep_time = Self::INTERIM_RESPONSE_RETRY_INTERVAL
-                        * (u32::pow(2, current_attempt as u32) - 1);
+                NetmdStatus::Interim if accept_interim => return Ok(data),
+                // The device is still busy with the command, so the final
+                // reply will come later
+                NetmdStatus::Interim | NetmdStatus::InTransition => {
+                    let sleep_time =
+                        self.interim_retry_interval * (u32::pow(2, current_attempt as u32) - 1);
 
-                    cross_sleep(Duration::from_millis(sleep_time as u64)).await;
+                    cross_sleep(sleep_time).await;
 
                     current_attempt += 1;
                     continue; // Retry!
                 }
-                NetmdStatus::Accepted | NetmdStatus::Implemented | NetmdStatus::Interim => {
-                    if current_attempt >= Self::MAX_INTERIM_READ_ATTEMPTS {
-                        return Err(InterfaceError::MaxRetries);
-                    }
-                    return Ok(data);
+                // `Changed` is the final reply to a notify command
+                NetmdStatus::Accepted | NetmdStatus::Implemented | NetmdStatus::Changed => {
+                    return Ok(data)
+                }
+                // The remaining statuses are only valid in commands
+                NetmdStatus::Control
+                | NetmdStatus::Status
+                | NetmdStatus::SpecificInquiry
+                | NetmdStatus::Notify
+                | NetmdStatus::GeneralInquiry => {
+                    return Err(InterfaceError::Unknown(self.command_reply(data)))
                 }
-                _ => return Err(InterfaceError::Unknown(format!("{:02X?}", data))),
             }
         }
 
-        // This should NEVER happen unless the code is changed wrongly
-        unreachable!("The max number of retries is set to 0!")
+        Err(InterfaceError::MaxRetries)
     }
 
     async fn playback_control(&mut self, action: Action) -> Result<(), InterfaceError> {
@@ -684,7 +1279,25 @@ impl NetMDInterface {
         self.playback_status_query(0x8802, 0x8806).await
     }
 
+    /// Get the first playback status block as a [`PlaybackStatus`]
+    pub async fn playback_status1_parsed(&mut self) -> Result<PlaybackStatus, InterfaceError> {
+        Ok(PlaybackStatus {
+            raw: self.playback_status1().await?,
+        })
+    }
+
+    /// Get the second playback status block as a [`PlaybackStatus`]
+    pub async fn playback_status2_parsed(&mut self) -> Result<PlaybackStatus, InterfaceError> {
+        Ok(PlaybackStatus {
+            raw: self.playback_status2().await?,
+        })
+    }
+
     /// Get the current playback position
+    ///
+    /// The values are the track number followed by the BCD decoded hour,
+    /// minute, second and frame within that track. See
+    /// [`NetMDInterface::position_parsed`] for a named version.
     pub async fn position(&mut self) -> Result<[u16; 5], InterfaceError> {
         self.change_descriptor_state(
             &Descriptor::OperatingStatusBlock,
@@ -722,6 +1335,19 @@ impl NetMDInterface {
         Ok(final_result)
     }
 
+    /// Get the current playback position as a [`Position`]
+    pub async fn position_parsed(&mut self) -> Result<Position, InterfaceError> {
+        let [track, hour, minute, second, frame] = self.position().await?;
+
+        Ok(Position {
+            track,
+            hour,
+            minute,
+            second,
+            frame,
+        })
+    }
+
     /// Eject the disc from the player if supported
     pub async fn eject_disc(&mut self) -> Result<(), InterfaceError> {
         let mut query = format_query("18c1 ff 6000".to_string(), vec![]).unwrap();
@@ -737,6 +1363,7 @@ impl NetMDInterface {
 
         match self.send_query(&mut query, true, false).await {
             Ok(_) => Ok(true),
+            Err(InterfaceError::Rejected(_) | InterfaceError::NotImplemented(_)) => Ok(false),
             Err(error) => Err(error),
         }
     }
@@ -758,7 +1385,23 @@ impl NetMDInterface {
         Ok(value as u16)
     }
 
-    /// Move the playback to a specific time
+    /// Move the playback to a specific track, first checking that the
+    /// track exists on the disc
+    pub async fn go_to_track_checked(&mut self, track_number: u16) -> Result<u16, InterfaceError> {
+        let count = self.track_count().await?;
+        if track_number >= count {
+            return Err(InterfaceError::InvalidTrack {
+                requested: track_number,
+                count,
+            });
+        }
+
+        self.go_to_track(track_number).await
+    }
+
+    /// Move the playback to a specific time.
+    ///
+    /// Returns the track and the position within it which the device moved to.
     pub async fn go_to_time(
         &mut self,
         track_number: u16,
@@ -766,7 +1409,7 @@ impl NetMDInterface {
         minute: u8,
         second: u8,
         frame: u8,
-    ) -> Result<u16, InterfaceError> {
+    ) -> Result<(u16, RawTime), InterfaceError> {
         let mut query = format_query(
             "1850 ff000000 0000 %w %B%B%B%B".to_string(),
             vec![
@@ -783,9 +1426,17 @@ impl NetMDInterface {
 
         let res = scan_query(reply, "1850 00000000 %?%? %w %B%B%B%B".to_string())?;
 
-        let value = res[0].to_i64().unwrap();
+        let track = res[0].to_i64().unwrap() as u16;
+        // The position the device actually moved to, which may be snapped
+        // to a different place than the one requested
+        let position = RawTime {
+            hours: res[1].to_i64().unwrap() as u64,
+            minutes: res[2].to_i64().unwrap() as u64,
+            seconds: res[3].to_i64().unwrap() as u64,
+            frames: res[4].to_i64().unwrap() as u64,
+        };
 
-        Ok(value as u16)
+        Ok((track, position))
     }
 
     pub async fn track_change(&mut self, direction: Direction) -> Result<(), InterfaceError> {
@@ -810,6 +1461,18 @@ impl NetMDInterface {
         Ok(())
     }
 
+    /// Write the TOC held in the device's memory to the disc.
+    ///
+    /// Title, group and track edits only change the TOC in memory, and
+    /// are not persistent until this is called or the disc is ejected.
+    pub async fn flush_toc(&mut self) -> Result<(), InterfaceError> {
+        let mut query = format_query("1808 10180200 00".to_string(), vec![])?;
+        let reply = self.send_query(&mut query, false, false).await?;
+        scan_query(reply, "1808 10180200 00".to_string())?;
+
+        Ok(())
+    }
+
     // TODO: Ensure this is returning the correct value, it
     // looks like it actually might be a 16 bit integer
     pub async fn disc_flags(&mut self) -> Result<u8, InterfaceError> {
@@ -850,8 +1513,19 @@ impl NetMDInterface {
         Ok(res[0].to_i64().unwrap() as u16)
     }
 
-    /// Gets the disc title as it is stored
+    /// Gets the disc title as it is stored.
+    ///
+    /// Bytes which aren't valid Shift JIS are replaced, use
+    /// [`NetMDInterface::raw_disc_title_bytes`] to get the title unchanged.
     pub async fn raw_disc_title(&mut self, wchar: bool) -> Result<String, InterfaceError> {
+        // Characters can be split between chunks, so the title is only
+        // decoded once it has been read in full
+        Ok(decode_title(&self.raw_disc_title_bytes(wchar).await?).0)
+    }
+
+    /// Gets the bytes of the disc title as it is stored, without decoding
+    /// them from Shift JIS
+    pub async fn raw_disc_title_bytes(&mut self, wchar: bool) -> Result<Vec<u8>, InterfaceError> {
         self.change_descriptor_state(&Descriptor::AudioContentsTD, &DescriptorAction::OpenRead)
             .await?;
         self.change_descriptor_state(&Descriptor::DiscTitleTD, &DescriptorAction::OpenRead)
@@ -860,9 +1534,7 @@ impl NetMDInterface {
         let mut done: i32 = 0;
         let mut remaining: i32 = 0;
         let mut total = 1;
-        let mut result: Vec<String> = Vec::new();
-        let mut chunksize;
-        let mut chunk;
+        let mut result: Vec<u8> = Vec::new();
 
         while done < total {
             let wchar_value = match wchar {
@@ -882,45 +1554,39 @@ impl NetMDInterface {
 
             let reply = self.send_query(&mut query, false, false).await?;
 
-            if remaining == 0 {
-                let res = scan_query(
-                    reply,
-                    "1806 02201801 00%? 3000 0a00 1000 %w0000 %?%?000a %w %*".to_string(),
-                )?;
-
-                chunksize = res[0].to_i64().unwrap() as i32;
-                total = res[1].to_i64().unwrap() as i32;
-                chunk = SHIFT_JIS.decode(&res[2].to_vec().unwrap()).0.into();
+            let (chunksize, chunk_total, chunk) = scan_disc_title_chunk(reply, remaining == 0)?;
+            if let Some(chunk_total) = chunk_total {
+                total = chunk_total;
+            }
 
-                chunksize -= 6;
-            } else {
-                let res = scan_query(
-                    reply,
-                    "1806 02201801 00%? 3000 0a00 1000 %w%?%? %*".to_string(),
-                )?;
-                chunksize = res[0].to_i64().unwrap() as i32;
-                chunk = SHIFT_JIS.decode(&res[1].to_vec().unwrap()).0.into();
+            // A chunk without any data would never finish the read
+            if chunksize <= 0 && done < total {
+                return Err(InterfaceError::TruncatedResponse);
             }
 
-            result.push(chunk);
+            result.extend(chunk);
             done += chunksize;
             remaining = total - done;
         }
 
-        let res = result.join("");
-
         self.change_descriptor_state(&Descriptor::DiscTitleTD, &DescriptorAction::Close)
             .await?;
         self.change_descriptor_state(&Descriptor::AudioContentsTD, &DescriptorAction::Close)
             .await?;
 
-        Ok(res)
+        Ok(result)
     }
 
     /// Gets the disc title
     pub async fn disc_title(&mut self, wchar: bool) -> Result<String, InterfaceError> {
-        let mut title = self.raw_disc_title(wchar).await?;
+        let raw_title = self.raw_disc_title(wchar).await?;
+
+        Ok(Self::disc_title_from_raw(raw_title, wchar))
+    }
 
+    /// Gets the disc title from a raw title read with
+    /// [`NetMDInterface::raw_disc_title`], without the groups
+    pub(crate) fn disc_title_from_raw(mut title: String, wchar: bool) -> String {
         let delim = match wchar {
             true => "／／",
             false => "//",
@@ -940,102 +1606,64 @@ impl NetMDInterface {
             }
         }
 
-        Ok(title)
+        title
     }
 
     /// Gets all groups on the disc
     pub async fn track_group_list(
         &mut self,
     ) -> Result<Vec<(Option<String>, Option<String>, Vec<u16>)>, InterfaceError> {
-        let raw_title = self.raw_disc_title(false).await?;
-        let group_list = raw_title.split("//");
-        let mut track_dict: HashMap<u16, (String, u16)> = HashMap::new();
-        let track_count = self.track_count().await?;
-        let mut result: Vec<(Option<String>, Option<String>, Vec<u16>)> = Vec::new();
+        self.track_group_list_inner(false).await
+    }
 
-        let raw_full_title = self.raw_disc_title(true).await?;
+    /// Get the groups of the disc like [`NetMDInterface::track_group_list`],
+    /// but skipping problems instead of returning an error.
+    ///
+    /// Groups with malformed ranges are dropped, and a track which is in
+    /// more than one group is kept in the first one only. If that leaves a
+    /// gap in a group, it is split into a group for each range of tracks.
+    pub async fn repaired_track_group_list(
+        &mut self,
+    ) -> Result<Vec<(Option<String>, Option<String>, Vec<u16>)>, InterfaceError> {
+        self.track_group_list_inner(true).await
+    }
 
-        let mut full_width_group_list = raw_full_title.split("／／");
-
-        for (i, group) in group_list.enumerate() {
-            if group.is_empty() {
-                continue;
-            }
-
-            if group.starts_with("0;") || group.find(';').is_none() || !raw_title.contains("//") {
-                continue;
-            }
-
-            let track_range: String = match group.split_once(';') {
-                Some(string) => string.0.to_string(),
-                None => return Err(InterfaceError::GroupError("no groups were found".into())),
-            };
-            if track_range.is_empty() {
-                continue;
-            }
-
-            let group_name = &group[track_range.len() + 1..];
-
-            let full_width_range = half_width_to_full_width_range(&track_range);
-
-            let full_width_group_name = full_width_group_list
-                .find(|n| n.starts_with(&full_width_range))
-                .unwrap()
-                .split_once('；')
-                .unwrap()
-                .1;
-
-            let mut track_minmax: Vec<&str> = Vec::new();
-            if track_range.find('-').is_some() {
-                track_minmax = track_range.split('-').collect();
-            } else {
-                track_minmax.push(track_range.as_str());
-            }
-
-            let (track_min, mut track_max) = (
-                track_minmax[0].parse::<u16>().unwrap(),
-                track_minmax[1].parse::<u16>().unwrap(),
-            );
-
-            track_max = u16::min(track_max, track_count);
-
-            // TODO: Do some error handling here
-            assert!(track_min <= track_max);
-
-            let mut track_list: Vec<u16> = Vec::new();
-            for track in track_min - 1..track_max {
-                if track_dict.contains_key(&track) {
-                    return Err(InterfaceError::GroupError(format!(
-                        "track {} is in 2 groups",
-                        track
-                    )));
-                }
-                track_dict.insert(track, (String::from(group_name), i as u16));
-                track_list.push(track);
-            }
-
-            result.push((
-                Some(String::from(group_name)),
-                Some(String::from(full_width_group_name)),
-                track_list.clone(),
-            ));
-        }
-
-        for i in 0..track_count {
-            if !track_dict.contains_key(&i) {
-                result.insert(0, (None, None, Vec::from([i])))
-            }
-        }
+    async fn track_group_list_inner(
+        &mut self,
+        repair: bool,
+    ) -> Result<Vec<(Option<String>, Option<String>, Vec<u16>)>, InterfaceError> {
+        let raw_title = self.raw_disc_title(false).await?;
+        let raw_full_title = self.raw_disc_title(true).await?;
+        let track_count = self.track_count().await?;
 
-        Ok(result)
+        parse_track_groups(&raw_title, &raw_full_title, track_count, repair)
     }
 
-    /// Gets a list of track titles from a set
+    /// Gets a list of track titles from a set.
+    ///
+    /// Bytes which aren't valid Shift JIS are replaced, use
+    /// [`NetMDInterface::track_title_bytes`] to get the titles unchanged.
     pub async fn track_titles(
         &mut self,
         tracks: Vec<u16>,
         wchar: bool,
     ) -> Result<Vec<String>, InterfaceError> {
+        Ok(self
+            .track_title_bytes(tracks, wchar)
+            .await?
+            .iter()
+            .map(|title| decode_title(title).0)
+            .collect())
+    }
+
+    /// Gets the bytes of the titles of a set of tracks as they are stored,
+    /// without decoding them from Shift JIS. Tracks without a title are
+    /// empty.
+    pub async fn track_title_bytes(
+        &mut self,
+        tracks: Vec<u16>,
+        wchar: bool,
+    ) -> Result<Vec<Vec<u8>>, InterfaceError> {
         let wchar_value = match wchar {
             true => 3,
             false => 2,
@@ -1049,7 +1677,7 @@ impl NetMDInterface {
         self.change_descriptor_state(&descriptor_type, &DescriptorAction::OpenRead)
             .await?;
 
-        let mut track_titles: Vec<String> = vec![];
+        let mut track_titles: Vec<Vec<u8>> = vec![];
         for i in tracks {
             let mut query = format_query(
                 "1806 022018%b %w 3000 0a00 ff00 00000000".to_string(),
@@ -1060,20 +1688,22 @@ impl NetMDInterface {
             )
             .unwrap();
 
-            let reply = self.send_query(&mut query, false, false).await?;
+            // Tracks without a title are rejected
+            let reply = match self.send_query(&mut query, false, false).await {
+                Ok(reply) => reply,
+                Err(InterfaceError::Rejected(_)) => {
+                    track_titles.push(Vec::new());
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
 
             let res = scan_query(
                 reply,
                 "1806 022018%? %?%? %?%? %?%? 1000 00%?0000 00%?000a %x".to_string(),
-            )
-            .unwrap();
+            )?;
 
-            track_titles.push(
-                encoding_rs::SHIFT_JIS
-                    .decode(&res[0].to_vec().unwrap())
-                    .0
-                    .into(),
-            )
+            track_titles.push(res[0].to_vec().unwrap())
         }
 
         self.change_descriptor_state(&descriptor_type, &DescriptorAction::Close)
@@ -1094,15 +1724,24 @@ impl NetMDInterface {
         Ok(title)
     }
 
-    // Sets the title of the disc
+    /// Sets the raw title of the disc.
+    ///
+    /// The raw title also holds the groups of the disc, which are lost if
+    /// they aren't part of `title`. Use [`NetMDContext::rename_disc`](super::commands::NetMDContext::rename_disc)
+    /// to change the title while keeping the groups.
+    ///
+    /// Nothing is written if the title is already set to `title`.
     pub async fn set_disc_title(&mut self, title: &str, wchar: bool) -> Result<(), InterfaceError> {
-        let current_title = self.raw_disc_title(wchar).await?;
-        if current_title == title {
-            return Err(InterfaceError::TitleError);
+        // The length of the title being replaced is taken from its bytes,
+        // as a title which isn't valid Shift JIS would change length when
+        // decoded
+        let current_title = self.raw_disc_title_bytes(wchar).await?;
+        if decode_title(&current_title) == (title.to_string(), false) {
+            return Ok(());
         }
 
         let new_title: Vec<u8>;
-        let old_len = length_after_encoding_to_sjis(&current_title);
+        let old_len = current_title.len();
 
         let wchar_value = match wchar {
             true => {
@@ -1175,18 +1814,15 @@ impl NetMDInterface {
 
         let new_len = new_title.len();
 
-        let old_len: u16 = match self.track_title(track, wchar).await {
-            Ok(current_title) => {
-                if title == current_title {
-                    return Ok(());
-                }
-                length_after_encoding_to_sjis(&current_title) as u16
-            }
-            Err(error) => match error {
-                InterfaceError::Rejected(_) => 0,
-                _ => return Err(error),
-            },
+        let current_title = match self.track_title_bytes([track].into(), wchar).await {
+            Ok(mut titles) => titles.remove(0),
+            Err(InterfaceError::Rejected(_)) => Vec::new(),
+            Err(error) => return Err(error),
         };
+        if decode_title(&current_title) == (title.to_string(), false) {
+            return Ok(());
+        }
+        let old_len = current_title.len() as u16;
 
         self.change_descriptor_state(&descriptor, &DescriptorAction::OpenWrite)
             .await?;
@@ -1247,28 +1883,43 @@ impl NetMDInterface {
         p1: i32,
         p2: i32,
     ) -> Result<Vec<u8>, InterfaceError> {
+        Ok(self.raw_tracks_info(&[track], p1, p2).await?.remove(0))
+    }
+
+    /// Gets raw track info for a set of tracks, opening the descriptor once
+    async fn raw_tracks_info(
+        &mut self,
+        tracks: &[u16],
+        p1: i32,
+        p2: i32,
+    ) -> Result<Vec<Vec<u8>>, InterfaceError> {
         self.change_descriptor_state(&Descriptor::AudioContentsTD, &DescriptorAction::OpenRead)
             .await?;
 
-        let mut query = format_query(
-            "1806 02201001 %w %w %w ff00 00000000".to_string(),
-            vec![
-                QueryValue::Number(track as i64),
-                QueryValue::Number(p1 as i64),
-                QueryValue::Number(p2 as i64),
-            ],
-        )?;
+        let mut results = Vec::with_capacity(tracks.len());
+        for track in tracks {
+            let mut query = format_query(
+                "1806 02201001 %w %w %w ff00 00000000".to_string(),
+                vec![
+                    QueryValue::Number(*track as i64),
+                    QueryValue::Number(p1 as i64),
+                    QueryValue::Number(p2 as i64),
+                ],
+            )?;
 
-        let reply = self.send_query(&mut query, false, false).await?;
-        let res = scan_query(
-            reply,
-            "1806 02201001 %?%? %?%? %?%? 1000 00%?0000 %x".to_string(),
-        )?;
+            let reply = self.send_query(&mut query, false, false).await?;
+            let res = scan_query(
+                reply,
+                "1806 02201001 %?%? %?%? %?%? 1000 00%?0000 %x".to_string(),
+            )?;
+
+            results.push(res[0].to_vec().unwrap());
+        }
 
         self.change_descriptor_state(&Descriptor::AudioContentsTD, &DescriptorAction::Close)
             .await?;
 
-        Ok(res[0].to_vec().unwrap())
+        Ok(results)
     }
 
     /// Gets the length of tracks as a raw duration from a set
@@ -1332,44 +1983,56 @@ impl NetMDInterface {
         &mut self,
         track_number: u16,
     ) -> Result<(Encoding, Channels), InterfaceError> {
-        let raw_value = self.raw_track_info(track_number, 0x3080, 0x0700).await?;
-        let result = scan_query(raw_value, "07 0004 0110 %b %b".to_string())?;
-
-        let encoding = match result[0].to_i64() {
-            Ok(0x90) => Encoding::SP,
-            Ok(0x92) => Encoding::LP2,
-            Ok(0x93) => Encoding::LP4,
-            Ok(e) => return Err(InterfaceError::InvalidEncoding(e as u8)),
-            Err(_) => unreachable!(),
-        };
+        Ok(self.track_encodings([track_number].into()).await?[0])
+    }
 
-        let channels = match result[1].to_i64() {
-            Ok(0x00) => Channels::Stereo,
-            Ok(0x01) => Channels::Mono,
-            Ok(e) => return Err(InterfaceError::InvalidDiscFormat(e as u8)),
-            Err(_) => unreachable!(),
-        };
+    /// Gets the encodings of a set of tracks
+    pub async fn track_encodings(
+        &mut self,
+        tracks: Vec<u16>,
+    ) -> Result<Vec<(Encoding, Channels)>, InterfaceError> {
+        let raw_values = self.raw_tracks_info(&tracks, 0x3080, 0x0700).await?;
+
+        raw_values
+            .into_iter()
+            .map(|raw_value| {
+                let result = scan_query(raw_value, "07 0004 0110 %b %b".to_string())?;
+
+                let encoding = Encoding::try_from(result[0].to_i64().unwrap() as u8)?;
+                let channels = Channels::try_from(result[1].to_i64().unwrap() as u8)?;
 
-        Ok((encoding, channels))
+                Ok((encoding, channels))
+            })
+            .collect()
     }
 
     /// Gets a track's flags
     pub async fn track_flags(&mut self, track: u16) -> Result<u8, InterfaceError> {
+        Ok(self.track_flags_list([track].into()).await?[0])
+    }
+
+    /// Gets the flags of a set of tracks
+    pub async fn track_flags_list(&mut self, tracks: Vec<u16>) -> Result<Vec<u8>, InterfaceError> {
         self.change_descriptor_state(&Descriptor::AudioContentsTD, &DescriptorAction::OpenRead)
             .await?;
 
-        let mut query = format_query(
-            "1806 01201001 %w ff00 00010008".to_string(),
-            vec![QueryValue::Number(track as i64)],
-        )?;
-        let reply = self.send_query(&mut query, false, false).await?;
+        let mut flags = Vec::with_capacity(tracks.len());
+        for track in tracks {
+            let mut query = format_query(
+                "1806 01201001 %w ff00 00010008".to_string(),
+                vec![QueryValue::Number(track as i64)],
+            )?;
+            let reply = self.send_query(&mut query, false, false).await?;
+
+            let res = scan_query(reply, "1806 01201001 %?%? 10 00 00010008 %b".to_string())?;
 
-        let res = scan_query(reply, "1806 01201001 %?%? 10 00 00010008 %b".to_string())?;
+            flags.push(res[0].to_i64().unwrap() as u8);
+        }
 
         self.change_descriptor_state(&Descriptor::AudioContentsTD, &DescriptorAction::Close)
             .await?;
 
-        Ok(res[0].to_i64().unwrap() as u8)
+        Ok(flags)
     }
 
     /// Gets the disc capacity as a [std::time::Duration]
@@ -1380,27 +2043,12 @@ impl NetMDInterface {
         let mut query = format_query("1806 02101000 3080 0300 ff00 00000000".to_string(), vec![])?;
         let reply = self.send_query(&mut query, false, false).await?;
 
-        // 8003 changed to %?03 - Panasonic returns 0803 instead. This byte's meaning is unknown
-        let res = scan_query(
-            reply,
-            "1806 02101000 3080 0300 1000 001d0000 001b %?03 0017 8000 0005 %W %B %B %B 0005 %W %B %B %B 0005 %W %B %B %B".to_string()
-        )?;
-
-        let res_num: Vec<RawTime> = res
-            .windows(4)
-            .step_by(4)
-            .map(|t| RawTime {
-                hours: t[0].to_i64().unwrap() as u64,
-                minutes: t[1].to_i64().unwrap() as u64,
-                seconds: t[2].to_i64().unwrap() as u64,
-                frames: t[3].to_i64().unwrap() as u64,
-            })
-            .collect();
+        let result = scan_disc_capacity_reply(reply);
 
         self.change_descriptor_state(&Descriptor::RootTD, &DescriptorAction::Close)
             .await?;
 
-        Ok(res_num.try_into().unwrap())
+        result
     }
 
     pub async fn recording_parameters(&mut self) -> Result<Vec<u8>, InterfaceError> {
@@ -1425,14 +2073,54 @@ impl NetMDInterface {
         Ok(res.into_iter().map(|x| x.to_i64().unwrap() as u8).collect())
     }
 
+    /// Gets the recording parameters decoded into a [`RecordingParameters`]
+    pub async fn recording_parameters_parsed(
+        &mut self,
+    ) -> Result<RecordingParameters, InterfaceError> {
+        let raw = self.recording_parameters().await?;
+
+        Ok(RecordingParameters {
+            encoding: Encoding::try_from(raw[0])?,
+            channels: Channels::try_from(raw[1])?,
+        })
+    }
+
     /// Gets the bytes of a track
     ///
-    /// This can only be executed on an MZ-RH1 / M200
+    /// This can only be executed on an MZ-RH1 / M200, see
+    /// [`NetMD::supports_upload`]
     pub async fn save_track_to_array<F: Fn(usize, usize)>(
         &mut self,
         track: u16,
         progress_callback: Option<F>,
     ) -> Result<(DiscFormat, u16, Vec<u8>), InterfaceError> {
+        let mut result = Vec::new();
+        let (format, frames) = self
+            .save_track_to_writer(track, progress_callback, &mut result, |_, _| Vec::new())
+            .await?;
+
+        Ok((format, frames, result))
+    }
+
+    /// Gets the bytes of a track, writing them to `writer` as they are read
+    ///
+    /// Once the format and length of the track are known, `header` is called
+    /// with them, and the bytes it returns are written before the track data.
+    ///
+    /// This can only be executed on an MZ-RH1 / M200, see
+    /// [`NetMD::supports_upload`]
+    pub async fn save_track_to_writer<F, W, H>(
+        &mut self,
+        track: u16,
+        progress_callback: Option<F>,
+        writer: &mut W,
+        header: H,
+    ) -> Result<(DiscFormat, u16), InterfaceError>
+    where
+        F: Fn(usize, usize),
+        W: std::io::Write,
+        H: FnOnce(DiscFormat, usize) -> Vec<u8>,
+    {
         let mut query = format_query(
             "1800 080046 f003010330 ff00 1001 %w".to_string(),
             vec![QueryValue::Number((track + 1) as i64)],
@@ -1449,9 +2137,15 @@ impl NetMDInterface {
         let codec = res[1].to_i64().unwrap() as u8;
         let length = res[2].to_i64().unwrap() as usize;
 
-        let result = self
-            .device
-            .read_bulk(length, 0x10000, progress_callback)
+        let format = codec::disc_format_from_codec(codec)?;
+
+        writer
+            .write_all(&header(format, length))
+            .map_err(|e| base::NetMDError::WriteFailed(e.kind()))?;
+
+        let chunk_size = self.device.bulk_read_chunk_size();
+        self.device
+            .read_bulk_to_writer(length, chunk_size, progress_callback, writer)
             .await?;
 
         scan_query(
@@ -1459,17 +2153,9 @@ impl NetMDInterface {
             "1800 080046 f003010330 0000 1001 %?%? %?%?".to_string(),
         )?;
 
-        cross_sleep(Duration::from_millis(500)).await;
+        cross_sleep(self.device.device_delay()).await;
 
-        let format: DiscFormat = match codec & 0x06 {
-            0 => DiscFormat::LP4,
-            2 => DiscFormat::LP2,
-            4 => DiscFormat::SPMono,
-            6 => DiscFormat::SPStereo,
-            e => return Err(InterfaceError::InvalidDiscFormat(e)),
-        };
-
-        Ok((format, frames, result))
+        Ok((format, frames))
     }
 
     pub async fn disable_new_track_protection(&mut self, val: u16) -> Result<(), InterfaceError> {
@@ -1505,13 +2191,13 @@ impl NetMDInterface {
     /// the root key.
     ///
     /// The leaf ID is a 8-byte constant
-    pub async fn leaf_id(&mut self) -> Result<[u8; 8], InterfaceError> {
+    pub async fn leaf_id(&mut self) -> Result<LeafId, InterfaceError> {
         let mut query = format_query("1800 080046 f0030103 11 ff".to_string(), vec![])?;
 
         let reply = self.send_query(&mut query, false, false).await?;
         let res = scan_query(reply, "1800 080046 f0030103 11 00 %*".to_string())?;
 
-        Ok(res[0].to_vec().unwrap().try_into().unwrap())
+        Ok(LeafId::try_from(res[0].to_vec().unwrap().as_slice())?)
     }
 
     pub async fn send_key_data(
@@ -1589,7 +2275,7 @@ impl NetMDInterface {
         &mut self,
         contentid: &[u8],
         keyenckey: &[u8],
-        hex_session_key: &[u8],
+        hex_session_key: &SessionKey,
     ) -> Result<(), InterfaceError> {
         if contentid.len() != 20 {
             return Err(EncryptionError::InvalidLength(
@@ -1603,15 +2289,9 @@ impl NetMDInterface {
                 keyenckey.len(),
             ))?;
         }
-        if hex_session_key.len() != 8 {
-            return Err(EncryptionError::InvalidLength(
-                "session key",
-                hex_session_key.len(),
-            ))?;
-        }
 
         let mut message = [vec![1, 1, 1, 1], contentid.to_vec(), keyenckey.to_vec()].concat();
-        DesCbcEnc::new(hex_session_key.into(), &[0u8; 8].into())
+        DesCbcEnc::new(hex_session_key.as_bytes().into(), &[0u8; 8].into())
             .encrypt_padded_mut::<NoPadding>(message.as_mut_slice(), 32)
             .unwrap();
 
@@ -1630,17 +2310,10 @@ impl NetMDInterface {
     pub async fn commit_track(
         &mut self,
         track_number: u16,
-        hex_session_key: &[u8],
+        hex_session_key: &SessionKey,
     ) -> Result<(), InterfaceError> {
-        if hex_session_key.len() != 8 {
-            return Err(EncryptionError::InvalidLength(
-                "hex session key",
-                hex_session_key.len(),
-            ))?;
-        }
-
         let mut message = [0u8; 8];
-        DesEcbEnc::new(hex_session_key.into())
+        DesEcbEnc::new(hex_session_key.as_bytes().into())
             .encrypt_padded_mut::<NoPadding>(&mut message, 8)
             .unwrap();
 
@@ -1664,38 +2337,28 @@ impl NetMDInterface {
         &mut self,
         wireformat: u8,
         discformat: u8,
-        frames: u32,
-        pkt_size: u32,
+        params: &TransferParams,
         // key, iv, data
-        mut packets: UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)>,
-        hex_session_key: &[u8],
+        packets: UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)>,
+        hex_session_key: &SessionKey,
         progress_callback: F,
     ) -> Result<(u16, Vec<u8>, Vec<u8>), InterfaceError>
     where
         F: Fn(usize, usize),
     {
-        if hex_session_key.len() != 8 {
-            return Err(EncryptionError::InvalidLength(
-                "hex session key",
-                hex_session_key.len(),
-            ))?;
-        }
-
         // Sharps are slow
-        cross_sleep(Duration::from_millis(200)).await;
-
-        let total_bytes: usize = (pkt_size + 24) as usize; //framesizedict[wireformat] * frames + pktcount * 24;
+        cross_sleep(self.device.device_delay()).await;
 
         let mut query = format_query(
             "1800 080046 f0030103 28 ff 000100 1001 ffff 00 %b %b %d %d".to_string(),
             vec![
                 QueryValue::Number(wireformat as i64),
                 QueryValue::Number(discformat as i64),
-                QueryValue::Number(frames as i64),
-                QueryValue::Number(total_bytes as i64),
+                QueryValue::Number(params.frames as i64),
+                QueryValue::Number(params.total_bytes as i64),
             ],
         )?;
-        let mut reply = self.send_query(&mut query, false, true).await?;
+        let reply = self.send_query(&mut query, false, true).await?;
         scan_query(
             reply,
             "1800 080046 f0030103 28 00 000100 1001 %?%? 00 %*".to_string(),
@@ -1703,21 +2366,41 @@ impl NetMDInterface {
         self.device.poll().await?;
 
         // Sharps are slow
-        cross_sleep(Duration::from_millis(200)).await;
+        cross_sleep(self.device.device_delay()).await;
 
-        let mut written_bytes = 0;
-        let mut packet_count = 0;
+        self.send_track_packets(params, packets, 0, hex_session_key, progress_callback)
+            .await
+    }
+
+    /// Write the packets of a track after [`NetMDInterface::send_track`] has
+    /// started the transfer, then read the result.
+    ///
+    /// To resume a transfer which failed part way through, pass the number
+    /// of bytes already written and packets from
+    /// [`resume_thread_encryptor`](super::encryption::resume_thread_encryptor).
+    pub async fn send_track_packets<F>(
+        &mut self,
+        params: &TransferParams,
+        // key, iv, data
+        mut packets: UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)>,
+        written_bytes: usize,
+        hex_session_key: &SessionKey,
+        progress_callback: F,
+    ) -> Result<(u16, Vec<u8>, Vec<u8>), InterfaceError>
+    where
+        F: Fn(usize, usize),
+    {
+        let total_bytes = params.total_bytes;
+        let mut written_bytes = written_bytes;
 
         while let Some((key, iv, data)) = packets.recv().await {
-            let binpack = if packet_count == 0 {
-                let packed_length: Vec<u8> = pkt_size.to_be_bytes().to_vec();
-                [vec![0, 0, 0, 0], packed_length, key, iv, data].concat()
+            let binpack = if written_bytes == 0 {
+                [params.packed_length().to_vec(), key, iv, data].concat()
             } else {
                 data
             };
             self.device.write_bulk(&binpack).await?;
             written_bytes += binpack.len();
-            packet_count += 1;
             (progress_callback)(total_bytes, written_bytes);
             if total_bytes == written_bytes {
                 packets.close();
@@ -1725,7 +2408,7 @@ impl NetMDInterface {
             }
         }
 
-        reply = self.read_reply(false).await?;
+        let reply = self.read_reply(false).await?;
         self.device.poll().await?;
         let res = scan_query(
             reply,
@@ -1733,7 +2416,7 @@ impl NetMDInterface {
         )?;
 
         let mut encrypted_data = res[1].to_vec().unwrap();
-        DesCbcDec::new(hex_session_key.into(), &[0u8; 8].into())
+        DesCbcDec::new(hex_session_key.as_bytes().into(), &[0u8; 8].into())
             .decrypt_padded_mut::<NoPadding>(&mut encrypted_data)
             .unwrap();
 
@@ -1743,7 +2426,8 @@ impl NetMDInterface {
         Ok((res[0].to_i64().unwrap() as u16, part1, part2))
     }
 
-    pub async fn track_uuid(&mut self, track: u16) -> Result<String, InterfaceError> {
+    /// Gets the UUID of a track, which is binary data
+    pub async fn track_uuid(&mut self, track: u16) -> Result<Vec<u8>, InterfaceError> {
         let mut query = format_query(
             "1800 080046 f0030103 23 ff 1001 %w".to_string(),
             vec![QueryValue::Number(track as i64)],
@@ -1752,7 +2436,7 @@ impl NetMDInterface {
 
         let res = scan_query(reply, "1800 080046 f0030103 23 00 1001 %?%? %*".to_string())?;
 
-        Ok(String::from_utf8_lossy(&res[0].to_vec().unwrap()).to_string())
+        Ok(res[0].to_vec().unwrap())
     }
 
     pub async fn terminate(&mut self) -> Result<(), InterfaceError> {
@@ -1841,12 +2525,43 @@ pub struct MDTrack {
     pub data: Vec<u8>,
     pub chunk_size: usize,
     pub full_width_title: Option<String>,
+    /// The content ID to check the track in with, [`MDTrack::HOMEBREW_CONTENT_ID`] if unset
+    pub content_id: Option<[u8; 20]>,
+    /// The key encryption key of the track, [`MDTrack::HOMEBREW_KEK`] if unset
+    pub kek: Option<[u8; 8]>,
 
     #[allow(clippy::type_complexity)]
     pub encrypt_packets_iterator:
         Box<dyn Fn(DataEncryptorInput) -> UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)>>,
 }
 
+/// The sizes of a track transfer, created by [`MDTrack::transfer_params`].
+///
+/// The track data is sent as `frames` frames of the wire format, padded to
+/// `pkt_size` bytes. The first packet is preceded by a header made of the
+/// packed length (`pkt_size` as a big-endian `u64`), the key and the IV, so
+/// `total_bytes` sent over USB is `pkt_size` plus [`Self::HEADER_SIZE`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferParams {
+    /// The number of frames in the track
+    pub frames: u32,
+    /// The size of the frame data, padded to a whole number of frames
+    pub pkt_size: u32,
+    /// The number of bytes written to the device, including the header
+    pub total_bytes: usize,
+}
+
+impl TransferParams {
+    /// The size of the header before the first packet
+    pub const HEADER_SIZE: usize = 24;
+
+    /// The length sent at the start of the first packet, `pkt_size` as a
+    /// 64 bit big-endian integer
+    pub fn packed_length(&self) -> [u8; 8] {
+        (self.pkt_size as u64).to_be_bytes()
+    }
+}
+
 pub struct DataEncryptorInput {
     pub kek: [u8; 8],
     pub frame_size: usize,
@@ -1855,8 +2570,26 @@ pub struct DataEncryptorInput {
 }
 
 impl MDTrack {
-    pub fn full_width_title(self) -> String {
-        self.full_width_title.unwrap_or("".to_string())
+    /// The content ID used for tracks which aren't checked in from elsewhere
+    pub const HOMEBREW_CONTENT_ID: [u8; 20] = [
+        0x01, 0x0f, 0x50, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x48, 0xa2, 0x8d, 0x3e, 0x1a, 0x3b,
+        0x0c, 0x44, 0xaf, 0x2f, 0xa0,
+    ];
+
+    /// The key encryption key used for tracks which aren't checked in from elsewhere
+    pub const HOMEBREW_KEK: [u8; 8] = [0x14, 0xe3, 0x83, 0x4e, 0xe2, 0xd3, 0xcc, 0xa5];
+
+    pub fn full_width_title(&self) -> Option<&str> {
+        self.full_width_title.as_deref()
+    }
+
+    /// Get the full width title, or create one from the half width title
+    /// if none is set
+    pub fn full_width_or_derived(&self) -> String {
+        match &self.full_width_title {
+            Some(title) => title.clone(),
+            None => sanitize_full_width_title(&self.title),
+        }
     }
 
     pub fn title(&self) -> String {
@@ -1867,6 +2600,8 @@ impl MDTrack {
         self.format.clone()
     }
 
+    /// The number of frames sent, counting a partial last frame as a whole
+    /// frame as it is padded with silence
     pub fn frame_count(&self) -> usize {
         self.total_size() / self.frame_size()
     }
@@ -1879,24 +2614,54 @@ impl MDTrack {
         self.chunk_size
     }
 
+    /// The size of the track data padded to a whole number of frames.
+    ///
+    /// This does not include the 24 byte header sent before the first packet.
     pub fn total_size(&self) -> usize {
-        let frame_size = self.frame_size();
-        let mut len = self.data.len();
-        if len % frame_size != 0 {
-            len = len + (frame_size - (len % frame_size));
+        self.data.len().next_multiple_of(self.frame_size())
+    }
+
+    /// Create a track of digital silence lasting `duration`, which is at
+    /// least a single frame long.
+    pub fn silence(title: &str, duration: RawTime) -> Self {
+        let format = WireFormat::Pcm;
+        let frames = format.frames_in(duration).max(1);
+
+        MDTrack {
+            title: title.to_string(),
+            data: vec![0; frames * format.frame_size() as usize],
+            format,
+            chunk_size: 0,
+            full_width_title: None,
+            content_id: None,
+            kek: None,
+            encrypt_packets_iterator: Box::new(new_thread_encryptor),
+        }
+    }
+
+    /// Whether the track has no audio data to send, which the device
+    /// can't handle
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The sizes sent to the device when starting the transfer of this track
+    pub fn transfer_params(&self) -> TransferParams {
+        let pkt_size = self.total_size();
+
+        TransferParams {
+            frames: self.frame_count() as u32,
+            pkt_size: pkt_size as u32,
+            total_bytes: pkt_size + TransferParams::HEADER_SIZE,
         }
-        len
     }
 
     pub fn content_id(&self) -> [u8; 20] {
-        [
-            0x01, 0x0f, 0x50, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x48, 0xa2, 0x8d, 0x3e, 0x1a,
-            0x3b, 0x0c, 0x44, 0xaf, 0x2f, 0xa0,
-        ]
+        self.content_id.unwrap_or(Self::HOMEBREW_CONTENT_ID)
     }
 
     pub fn get_kek(&self) -> [u8; 8] {
-        [0x14, 0xe3, 0x83, 0x4e, 0xe2, 0xd3, 0xcc, 0xa5]
+        self.kek.unwrap_or(Self::HOMEBREW_KEK)
     }
 
     pub fn get_encrypting_iterator(&mut self) -> UnboundedReceiver<(Vec<u8>, Vec<u8>, Vec<u8>)> {
@@ -1909,16 +2674,24 @@ impl MDTrack {
     }
 }
 
+/// The metadata of a started [`MDSession`], see [`MDSession::info`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub leaf_id: LeafId,
+    pub session_key: SessionKey,
+}
+
 pub struct MDSession<'a> {
     pub md: &'a mut NetMDInterface,
     pub ekb_object: EKBOpenSource,
-    pub hex_session_key: Option<Vec<u8>>,
+    pub hex_session_key: Option<SessionKey>,
+    pub leaf_id: Option<LeafId>,
 }
 
 impl<'a> MDSession<'a> {
-    pub async fn init(&mut self) -> Result<(), Box<dyn Error>> {
+    pub async fn init(&mut self) -> Result<(), InterfaceError> {
         self.md.enter_secure_session().await?;
-        self.md.leaf_id().await?;
+        self.leaf_id = Some(self.md.leaf_id().await?);
 
         let ekb = self.ekb_object.ekb_data_for_leaf_id();
         self.md
@@ -1935,30 +2708,43 @@ impl<'a> MDSession<'a> {
         let mut devnonce = self.md.session_key_exchange(nonce.clone()).await?;
         nonce.append(&mut devnonce);
 
-        self.hex_session_key = Some(retailmac(&self.ekb_object.root_key(), &nonce, &[0u8; 8]));
+        let session_key = retailmac(&self.ekb_object.root_key(), &nonce, &[0u8; 8]);
+        self.hex_session_key = Some(SessionKey::try_from(session_key.as_slice())?);
         Ok(())
     }
 
-    pub async fn close(&mut self) -> Result<(), Box<dyn Error>> {
+    pub async fn close(&mut self) -> Result<(), InterfaceError> {
         if self.hex_session_key.is_none() {
             self.md.session_key_forget().await?;
         }
         self.hex_session_key = None;
+        self.leaf_id = None;
 
         Ok(())
     }
 
+    /// Get the leaf ID and session key of the session, if it was started
+    pub fn info(&self) -> Option<SessionInfo> {
+        Some(SessionInfo {
+            leaf_id: self.leaf_id?,
+            session_key: self.hex_session_key?,
+        })
+    }
+
     pub async fn download_track<F>(
         &mut self,
         mut track: MDTrack,
         progress_callback: F,
         disc_format: Option<DiscFormat>,
-    ) -> Result<(u16, Vec<u8>, Vec<u8>), Box<dyn Error>>
+    ) -> Result<(u16, Vec<u8>, Vec<u8>), InterfaceError>
     where
         F: Fn(usize, usize),
     {
         if self.hex_session_key.is_none() {
-            return Err("Cannot download a track using a non-init()'ed session!".into());
+            return Err(InterfaceError::SessionNotStarted);
+        }
+        if track.is_empty() {
+            return Err(InterfaceError::EmptyTrack);
         }
         self.md
             .setup_download(
@@ -1975,10 +2761,9 @@ impl<'a> MDSession<'a> {
             .send_track(
                 data_format as u8,
                 final_disc_format as u8,
-                track.frame_count() as u32,
-                track.total_size() as u32,
+                &track.transfer_params(),
                 track.get_encrypting_iterator(),
-                self.hex_session_key.as_ref().unwrap().as_slice(),
+                self.hex_session_key.as_ref().unwrap(),
                 progress_callback,
             )
             .await?;
@@ -1986,9 +2771,9 @@ impl<'a> MDSession<'a> {
         self.md
             .set_track_title(track_index, &track.title, false)
             .await?;
-        if let Some(full_width) = track.full_width_title {
+        if let Some(full_width) = track.full_width_title() {
             self.md
-                .set_track_title(track_index, &full_width, true)
+                .set_track_title(track_index, full_width, true)
                 .await?;
         }
         self.md
@@ -2003,6 +2788,383 @@ impl<'a> MDSession<'a> {
             md,
             ekb_object: EKBOpenSource,
             hex_session_key: None,
+            leaf_id: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn construct_multibyte_reads_big_endian() {
+        let mut offset = 1;
+        let value = construct_multibyte(&[0xff, 0x12, 0x34, 0x56], 3, &mut offset).unwrap();
+        assert_eq!(value, 0x123456);
+        assert_eq!(offset, 4);
+    }
+
+    #[test]
+    fn construct_multibyte_truncated_buffer() {
+        let mut offset = 2;
+        let result = construct_multibyte(&[0x00, 0x01, 0x02], 2, &mut offset);
+        assert!(matches!(result, Err(InterfaceError::TruncatedResponse)));
+
+        let mut offset = 0;
+        let result = construct_multibyte(&[], 1, &mut offset);
+        assert!(matches!(result, Err(InterfaceError::TruncatedResponse)));
+    }
+
+    #[test]
+    fn frames_in_every_wire_format() {
+        let second = RawTime::from_frames(512);
+        let minute = RawTime::from_frames(512 * 60);
+
+        // A frame of every format is 512 samples, so the count is the same
+        // and only the number of bytes differs with the bitrate
+        for (format, bytes_per_minute) in [
+            (WireFormat::Pcm, 10_582_016),
+            (WireFormat::LP2, 992_064),
+            (WireFormat::L105kbps, 785_384),
+            (WireFormat::LP4, 496_032),
+        ] {
+            assert_eq!(format.frames_in(RawTime::from_frames(0)), 0);
+            assert_eq!(format.frames_in(second), 86);
+            assert_eq!(format.frames_in(minute), 5167);
+            assert_eq!(
+                format.frames_in(minute) * format.frame_size() as usize,
+                bytes_per_minute
+            );
+        }
+    }
+
+    #[test]
+    fn playback_status_state() {
+        let status = PlaybackStatus {
+            raw: vec![0x00, 0x10, 0x00, 0x00, 0xc5, 0xff, 0x00],
+        };
+        assert_eq!(status.state(), Some(50687));
+
+        let short = PlaybackStatus {
+            raw: vec![0x00, 0x10, 0x00, 0x00, 0xc5],
+        };
+        assert_eq!(short.state(), None);
+    }
+
+    fn disc_title_reply(chunksize: u16, total: u16, title: &[u8]) -> Vec<u8> {
+        format_query(
+            "09 1806 02201801 0000 3000 0a00 1000 %w0000 0000 000a %w %*".to_string(),
+            vec![
+                QueryValue::Number(chunksize as i64),
+                QueryValue::Number(total as i64),
+                QueryValue::Array(title.to_vec()),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn disc_title_chunk() {
+        let reply = disc_title_reply(6 + 4, 4, b"Disc");
+        let (size, total, data) = scan_disc_title_chunk(reply, true).unwrap();
+
+        assert_eq!(size, 4);
+        assert_eq!(total, Some(4));
+        assert_eq!(data, b"Disc");
+    }
+
+    #[test]
+    fn disc_title_chunk_huge_total() {
+        for total in [MAX_TITLE_LENGTH as u16 + 1, 0x7fff, 0xffff] {
+            let reply = disc_title_reply(6 + 4, total, b"Disc");
+
+            assert!(matches!(
+                scan_disc_title_chunk(reply, true),
+                Err(InterfaceError::TruncatedResponse)
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_disc_title_is_decoded_lossily() {
+        let reply = disc_title_reply(6 + 5, 5, b"Disc\x82");
+        let (_, _, data) = scan_disc_title_chunk(reply, true).unwrap();
+
+        assert_eq!(decode_title(&data), (String::from("Disc\u{FFFD}"), true));
+        assert_eq!(decode_title(b"Disc"), (String::from("Disc"), false));
+    }
+
+    #[test]
+    fn disc_title_split_between_chunks() {
+        let first = disc_title_reply(6 + 5, 6, b"Disc\x82");
+        let second = format_query(
+            "09 1806 02201801 0000 3000 0a00 1000 %w0000 %*".to_string(),
+            vec![QueryValue::Number(1), QueryValue::Array(vec![0xa0])],
+        )
+        .unwrap();
+
+        let (_, _, mut data) = scan_disc_title_chunk(first, true).unwrap();
+        data.extend(scan_disc_title_chunk(second, false).unwrap().2);
+
+        assert_eq!(decode_title(&data), (String::from("Discあ"), false));
+    }
+
+    fn md_track(format: WireFormat, len: usize) -> MDTrack {
+        MDTrack {
+            title: String::from("Test"),
+            format,
+            data: vec![0; len],
+            chunk_size: 0,
+            full_width_title: None,
+            content_id: None,
+            kek: None,
+            encrypt_packets_iterator: Box::new(new_thread_encryptor),
+        }
+    }
+
+    #[test]
+    fn pcm_track_framing() {
+        // One second of 16 bit stereo audio at 44.1kHz
+        let track = md_track(WireFormat::Pcm, 44100 * 4);
+
+        assert_eq!(track.frame_size(), 2048);
+        assert_eq!(track.frame_count(), 87);
+        assert_eq!(track.total_size(), 87 * 2048);
+    }
+
+    #[test]
+    fn mono_sp_track_framing() {
+        // Ten seconds of mono audio, sent as stereo PCM with each sample
+        // duplicated into both channels to be recorded as SP mono
+        let mono: Vec<u8> = (0..44100 * 10)
+            .flat_map(|i: u32| (i as u16).to_be_bytes())
+            .collect();
+        let stereo: Vec<u8> = mono.chunks(2).flat_map(|s| [s, s].concat()).collect();
+        let mut track = md_track(WireFormat::Pcm, 0);
+        track.data = stereo;
+
+        // The frames are the same as for a stereo track of that duration,
+        // with the last partial frame padded
+        assert_eq!(track.frame_count(), 862);
+        assert_eq!(track.total_size(), 862 * 2048);
+        assert_eq!(
+            track.frame_count(),
+            md_track(WireFormat::Pcm, 44100 * 10 * 4).frame_count()
+        );
+        assert_eq!(
+            WireFormat::Pcm.frames_in(RawTime::from_frames(512 * 10)),
+            861
+        );
+    }
+
+    #[test]
+    fn transfer_params_known_size() {
+        // An LP2 file of exactly 1376 frames, and one a byte longer
+        let track = md_track(WireFormat::LP2, 1376 * 192);
+        assert_eq!(
+            track.transfer_params(),
+            TransferParams {
+                frames: 1376,
+                pkt_size: 264_192,
+                total_bytes: 264_216,
+            }
+        );
+
+        let track = md_track(WireFormat::LP2, 1376 * 192 + 1);
+        assert_eq!(
+            track.transfer_params(),
+            TransferParams {
+                frames: 1377,
+                pkt_size: 264_384,
+                total_bytes: 264_408,
+            }
+        );
+    }
+
+    #[test]
+    fn packed_length_is_big_endian_u64() {
+        let params = md_track(WireFormat::LP2, 1376 * 192).transfer_params();
+        assert_eq!(params.packed_length(), [0, 0, 0, 0, 0, 0x04, 0x08, 0x00]);
+
+        let params = TransferParams {
+            frames: 0,
+            pkt_size: 0x1234_5678,
+            total_bytes: 0,
+        };
+        assert_eq!(params.packed_length(), [0, 0, 0, 0, 0x12, 0x34, 0x56, 0x78]);
+    }
+
+    #[test]
+    fn empty_track_and_silence() {
+        assert!(md_track(WireFormat::Pcm, 0).is_empty());
+        assert!(!md_track(WireFormat::Pcm, 1).is_empty());
+
+        // Even a zero length silence is a single frame
+        let silence = MDTrack::silence("Silence", RawTime::from_frames(0));
+        assert!(!silence.is_empty());
+        assert_eq!(silence.frame_count(), 1);
+        assert!(silence.data.iter().all(|&b| b == 0));
+
+        let silence = MDTrack::silence("Silence", RawTime::from_frames(512));
+        assert_eq!(silence.frame_count(), 86);
+        assert_eq!(silence.total_size(), 86 * 2048);
+    }
+
+    /// The named groups from [`parse_track_groups`]
+    fn named_groups(
+        groups: &[(Option<String>, Option<String>, Vec<u16>)],
+    ) -> Vec<(&str, Vec<u16>)> {
+        groups
+            .iter()
+            .filter_map(|(name, _, tracks)| Some((name.as_deref()?, tracks.clone())))
+            .collect()
+    }
+
+    #[test]
+    fn repair_overlapping_groups() {
+        let raw_title = "0;Disc//3-5;A//1-8;B//";
+        assert!(parse_track_groups(raw_title, "", 8, false).is_err());
+
+        // B loses the tracks of A, leaving two separate ranges
+        let groups = parse_track_groups(raw_title, "", 8, true).unwrap();
+        assert_eq!(
+            named_groups(&groups),
+            [
+                ("A", vec![2, 3, 4]),
+                ("B", vec![0, 1]),
+                ("B", vec![5, 6, 7])
+            ]
+        );
+        assert_eq!(groups.len(), 3);
+    }
+
+    #[test]
+    fn repair_out_of_range_groups() {
+        let raw_title = "0;Disc//1-2;A//7-12;B//10-12;C//x-3;D//";
+        assert!(parse_track_groups(raw_title, "", 8, false).is_err());
+
+        // B is cut off at the last track, C and D are dropped
+        let groups = parse_track_groups(raw_title, "", 8, true).unwrap();
+        assert_eq!(
+            named_groups(&groups),
+            [("A", vec![0, 1]), ("B", vec![6, 7])]
+        );
+
+        let ungrouped: Vec<u16> = groups
+            .iter()
+            .filter(|(name, _, _)| name.is_none())
+            .flat_map(|(_, _, tracks)| tracks.clone())
+            .collect();
+        assert_eq!(ungrouped, [5, 4, 3, 2]);
+    }
+
+    #[test]
+    fn replace_incomplete_keeps_the_cause() {
+        use std::error::Error;
+
+        let error = InterfaceError::ReplaceIncomplete {
+            index: 4,
+            source: Box::new(InterfaceError::Timeout),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "the new track was added, but the old track is still on the disc as track 4"
+        );
+        assert_eq!(
+            error.source().unwrap().to_string(),
+            InterfaceError::Timeout.to_string()
+        );
+    }
+
+    #[test]
+    fn track_flag_ignores_unknown_bits() {
+        assert!(matches!(
+            TrackFlag::from_flags(0x00),
+            TrackFlag::Unprotected
+        ));
+        assert!(matches!(TrackFlag::from_flags(0x03), TrackFlag::Protected));
+        assert!(matches!(
+            TrackFlag::from_flags(0x03 | 0xf0),
+            TrackFlag::Protected
+        ));
+        assert!(matches!(
+            TrackFlag::from_flags(0xf0),
+            TrackFlag::Unprotected
+        ));
+    }
+
+    fn disc_capacity_reply(variant: &str, trailing: &[u8]) -> Vec<u8> {
+        let format = format!(
+            "09 1806 02101000 3080 0300 1000 001d0000 001b {variant} 0017 8000 \
+             0005 %W %B %B %B 0005 %W %B %B %B 0005 %W %B %B %B %*"
+        );
+        let args = [0, 10, 30, 0, 1, 20, 0, 0, 1, 9, 30, 0]
+            .into_iter()
+            .map(QueryValue::Number)
+            .chain([QueryValue::Array(trailing.to_vec())])
+            .collect();
+
+        format_query(format, args).unwrap()
+    }
+
+    #[test]
+    fn disc_capacity_sony_and_panasonic() {
+        let expected = [
+            RawTime::from_frames((10 * 60 + 30) * 512),
+            RawTime::from_frames(80 * 60 * 512),
+            RawTime::from_frames((69 * 60 + 30) * 512),
+        ];
+
+        let sony = disc_capacity_reply("8003", &[]);
+        assert_eq!(scan_disc_capacity_reply(sony).unwrap(), expected);
+
+        let panasonic = disc_capacity_reply("0803", &[0x00, 0x00]);
+        assert_eq!(scan_disc_capacity_reply(panasonic).unwrap(), expected);
+
+        let unknown = disc_capacity_reply("0303", &[]);
+        assert!(scan_disc_capacity_reply(unknown).is_err());
+    }
+
+    #[test]
+    fn disc_capacity_needs_exactly_one_marker() {
+        let neither = disc_capacity_reply("", &[]);
+        assert!(scan_disc_capacity_reply(neither).is_err());
+
+        let both = disc_capacity_reply("8003 0803", &[]);
+        assert!(scan_disc_capacity_reply(both).is_err());
+    }
+
+    #[test]
+    fn partial_frames_are_padded() {
+        let frames = 10;
+
+        for format in [
+            WireFormat::Pcm,
+            WireFormat::LP2,
+            WireFormat::L105kbps,
+            WireFormat::LP4,
+        ] {
+            let frame_size = format.frame_size() as usize;
+
+            for (len, expected_frames) in [
+                (frames * frame_size, frames),
+                (frames * frame_size + 1, frames + 1),
+                (frames * frame_size - 1, frames),
+            ] {
+                let mut track = md_track(format.clone(), len);
+                assert_eq!(track.frame_count(), expected_frames);
+                assert_eq!(track.total_size(), expected_frames * frame_size);
+
+                // The encryptor pads the data the same way
+                let mut packets = track.get_encrypting_iterator();
+                let mut sent = 0;
+                while let Some((_, _, data)) = packets.blocking_recv() {
+                    sent += data.len();
+                }
+                assert_eq!(sent, expected_frames * frame_size);
+            }
         }
     }
 }
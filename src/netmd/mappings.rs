@@ -9,7 +9,7 @@ pub static MAPPINGS_JP: phf::Map<&str, &str> = phf_map![
     "%" =>"％",
     "&" =>"＆",
     "'" =>"＇",
-    "" =>"（",
+    "(" =>"（",
     ")" =>"）",
     "*" =>"＊",
     "+" =>"＋",
@@ -379,7 +379,7 @@ pub static MAPPINGS_HW: phf::Map<&str, &str> = phf_map![
     "％" =>"%",
     "＆" =>"&",
     "＇" =>"'",
-    "（" =>"",
+    "（" =>"(",
     "）" =>")",
     "＊" =>"*",
     "＋" =>"+",
@@ -563,7 +563,7 @@ pub static ALLOWED_HW_KANA: &[&str] = &[
     "ﾁﾞ", "ｯ", "ﾂ", "ﾂﾞ", "ﾃ", "ﾃﾞ", "ﾄ", "ﾄﾞ", "ﾅ", "ﾆ", "ﾇ", "ﾈ", "ﾉ", "ﾊ", "ﾊﾞ", "ﾊﾟ", "ﾋ",
     "ﾋﾞ", "ﾋﾟ", "ﾌ", "ﾌﾞ", "ﾌﾟ", "ﾍ", "ﾍﾞ", "ﾍﾟ", "ﾎ", "ﾎﾞ", "ﾎﾟ", "ﾏ", "ﾐ", "ﾑ", "ﾒ", "ﾓ", "ｬ",
     "ﾔ", "ｭ", "ﾕ", "ｮ", "ﾖ", "ﾗ", "ﾘ", "ﾙ", "ﾚ", "ﾛ", "ﾜ", "ｦ", "ﾝ", "-", "ヮ", "ヰ", "ヱ", "ヵ",
-    "ヶ", "ｳﾞ", "ヽ", "ヾ", "･", "｢", "｣", "｡", "､", "!", "\"", "#", "$", "%", "&", "'", "", ")",
+    "ヶ", "ｳﾞ", "ヽ", "ヾ", "･", "｢", "｣", "｡", "､", "!", "\"", "#", "$", "%", "&", "'", "(", ")",
     "*", "+", ",", ".", "/", ":", ";", "<", "=", ">", "?", "@", "A", "B", "C", "D", "E", "F", "G",
     "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z",
     "[", "\\", "]", "^", "_", "`", "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m",
@@ -573,10 +573,12 @@ pub static ALLOWED_HW_KANA: &[&str] = &[
     "ｾﾞ", "ｿ", "ｿﾞ", "ﾀ", "ﾀﾞ", "ﾁ", "ﾁﾞ", "ｯ", "ﾂ", "ﾂﾞ", "ﾃ", "ﾃﾞ", "ﾄ", "ﾄﾞ", "ﾅ", "ﾆ", "ﾇ",
     "ﾈ", "ﾉ", "ﾊ", "ﾊﾞ", "ﾊﾟ", "ﾋ", "ﾋﾞ", "ﾋﾟ", "ﾌ", "ﾌﾞ", "ﾌﾟ", "ﾍ", "ﾍﾞ", "ﾍﾟ", "ﾎ", "ﾎﾞ", "ﾎﾟ",
     "ﾏ", "ﾐ", "ﾑ", "ﾒ", "ﾓ", "ｬ", "ﾔ", "ｭ", "ﾕ", "ｮ", "ﾖ", "ﾗ", "ﾘ", "ﾙ", "ﾚ", "ﾛ", "ﾜ", "ｦ", "ﾝ",
-    "ヮ", "ヰ", "ヱ", "ヵ", "ヶ", "ｳﾞ", "ヽ", "ヾ",
+    "ヮ", "ヰ", "ヱ", "ヵ", "ヶ", "ｳﾞ", "ヽ", "ヾ", "ﾞ", "ﾟ",
 ];
 
-/// Characters which take up more than one byte
+/// Characters which take up more than one byte, with the number of extra
+/// bytes they need. These are the voiced kana which become a base character
+/// and a separate (han)dakuten mark in half width titles.
 pub static MULTI_BYTE_CHARS: phf::Map<char, u8> = phf_map![
     'ガ' => 1,
     'ギ' => 1,
@@ -2,9 +2,18 @@
 //! devices.
 
 pub mod base;
+mod codec;
 pub mod commands;
 pub mod encryption;
+pub mod factory_commands;
+pub mod factory_interface;
 pub mod interface;
 mod mappings;
+pub mod patches;
 mod query_utils;
 mod utils;
+
+#[doc(inline)]
+pub use commands::{DeviceStatus, Disc, Group, Track};
+#[doc(inline)]
+pub use interface::{Channels, DiscFormat, Encoding, MDTrack, WireFormat};
@@ -0,0 +1,60 @@
+//! A registry of firmware patches which can be applied through the
+//! [`NetMDFactoryInterface`](super::factory_interface::NetMDFactoryInterface).
+//!
+//! Patches are specific to a firmware, so each one is registered under the
+//! descriptive device code it was written for, such as `S1.600`, as
+//! returned by [`get_descriptive_device_code`](super::factory_commands::get_descriptive_device_code).
+//!
+//! Applying and reverting patches needs the `factory-writes` feature.
+
+use std::collections::HashMap;
+
+/// The name a [`Patch`] is registered under
+pub type PatchName = &'static str;
+
+/// The base address of the patch peripheral, which differs between chips
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatchPeripheralBase(pub u32);
+
+/// A single patch to the mapped memory of a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    pub base: PatchPeripheralBase,
+    /// The offset of the patch from the base address
+    pub offset: u32,
+    /// The bytes expected at the address before the patch is applied
+    pub original_bytes: Vec<u8>,
+    /// The bytes written when the patch is applied
+    pub patched_bytes: Vec<u8>,
+}
+
+impl Patch {
+    /// The address in mapped memory the patch is written to
+    pub fn address(&self) -> u32 {
+        self.base.0 + self.offset
+    }
+}
+
+/// Patches for each device code
+#[derive(Debug, Clone, Default)]
+pub struct PatchRegistry {
+    patches: HashMap<(String, PatchName), Patch>,
+}
+
+impl PatchRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a patch for a device code, replacing any patch with the
+    /// same name for that device code
+    pub fn insert(&mut self, device_code: &str, name: PatchName, patch: Patch) {
+        self.patches.insert((device_code.to_string(), name), patch);
+    }
+
+    /// Get the patch registered under `name` for a device code
+    pub fn get(&self, device_code: &str, name: PatchName) -> Option<&Patch> {
+        self.patches.get(&(device_code.to_string(), name))
+    }
+}
@@ -8,6 +8,10 @@ use thiserror::Error;
 /// %* - raw Uint8Array
 /// %B - BCD-encoded 1-byte number
 /// %W - BCD-encoded 2-byte number
+/// %( ... )? - an optional group, only for scanning. The contents are matched
+///             if the reply contains them, and skipped otherwise. Groups can't
+///             be nested, and the group is only skipped if the reply doesn't
+///             match it, errors elsewhere in the reply are still returned
 static FORMAT_TYPE_LEN_DICT: phf::Map<char, i32> = phf::phf_map! {
     'b' => 1, // byte
     'w' => 2, // word
@@ -97,6 +101,43 @@ pub enum QueryError {
         actual: u8,
         format_string: String,
     },
+
+    #[error("format expects {expected} arguments, got {got}")]
+    ArgCountMismatch { expected: usize, got: usize },
+
+    #[error("{0} unexpected bytes were left after the end of the format")]
+    TrailingData(usize),
+
+    #[error("the input ended before the end of the format")]
+    UnexpectedEnd,
+
+    #[error("optional group is not closed with `)?` (format {0})")]
+    UnclosedGroup(String),
+}
+
+/// Count the number of arguments a format string for [`format_query`] consumes
+fn expected_arg_count(format: &str) -> usize {
+    let mut count = 0;
+    let mut escaped = false;
+
+    for character in format.chars() {
+        if escaped {
+            if ['<', '>'].contains(&character) {
+                continue;
+            }
+            escaped = false;
+
+            if FORMAT_TYPE_LEN_DICT.contains_key(&character)
+                || ['x', 's', 'z', '*', 'B', 'W'].contains(&character)
+            {
+                count += 1;
+            }
+        } else if character == '%' {
+            escaped = true;
+        }
+    }
+
+    count
 }
 
 /// Formats a query using a standard input to send to the player
@@ -105,6 +146,12 @@ pub fn format_query(format: String, args: Vec<QueryValue>) -> Result<Vec<u8>, Qu
         println!("SENT>>> F: {}", format);
     }
 
+    let expected = expected_arg_count(&format);
+    let got = args.len();
+    if expected != got {
+        return Err(QueryError::ArgCountMismatch { expected, got });
+    }
+
     let mut result: Vec<u8> = Vec::new();
     let mut half: Option<char> = None;
     let mut arg_stack = args.into_iter();
@@ -121,27 +168,31 @@ pub fn format_query(format: String, args: Vec<QueryValue>) -> Result<Vec<u8>, Qu
 
             match character {
                 character if FORMAT_TYPE_LEN_DICT.contains_key(&character) => {
-                    let value = arg_stack.next().unwrap().to_i64().unwrap();
-                    match character {
-                        'b' => result.push(value as u8),
-                        'w' => {
-                            let mut value_bytes = (value as i16).to_be_bytes().to_vec();
-                            result.append(&mut value_bytes)
-                        }
-                        'd' => {
-                            let mut value_bytes = (value as i32).to_be_bytes().to_vec();
-                            result.append(&mut value_bytes)
-                        }
-                        'q' => {
-                            let mut value_bytes = value.to_be_bytes().to_vec();
-                            result.append(&mut value_bytes)
-                        }
-                        _ => (),
+                    let value = arg_stack
+                        .next()
+                        .ok_or(QueryError::ArgCountMismatch { expected, got })?
+                        .to_i64()
+                        .unwrap();
+                    let little_endian = endianness_override == Some('<');
+                    let mut value_bytes = match character {
+                        'b' => vec![value as u8],
+                        'w' if little_endian => (value as i16).to_le_bytes().to_vec(),
+                        'w' => (value as i16).to_be_bytes().to_vec(),
+                        'd' if little_endian => (value as i32).to_le_bytes().to_vec(),
+                        'd' => (value as i32).to_be_bytes().to_vec(),
+                        'q' if little_endian => value.to_le_bytes().to_vec(),
+                        'q' => value.to_be_bytes().to_vec(),
+                        _ => unreachable!(),
                     };
+                    result.append(&mut value_bytes);
                     endianness_override = None;
                 }
                 character if character == 'x' || character == 's' || character == 'z' => {
-                    let mut array_value = arg_stack.next().unwrap().to_vec().unwrap();
+                    let mut array_value = arg_stack
+                        .next()
+                        .ok_or(QueryError::ArgCountMismatch { expected, got })?
+                        .to_vec()
+                        .unwrap();
 
                     let mut array_length = array_value.len();
 
@@ -159,11 +210,19 @@ pub fn format_query(format: String, args: Vec<QueryValue>) -> Result<Vec<u8>, Qu
                     }
                 }
                 '*' => {
-                    let mut array_value = arg_stack.next().unwrap().to_vec().unwrap();
+                    let mut array_value = arg_stack
+                        .next()
+                        .ok_or(QueryError::ArgCountMismatch { expected, got })?
+                        .to_vec()
+                        .unwrap();
                     result.append(&mut array_value);
                 }
                 character if character == 'B' || character == 'W' => {
-                    let value = arg_stack.next().unwrap().to_i64().unwrap();
+                    let value = arg_stack
+                        .next()
+                        .ok_or(QueryError::ArgCountMismatch { expected, got })?
+                        .to_i64()
+                        .unwrap();
                     let converted = utils::int_to_bcd(value as i32);
                     if character == 'W' {
                         result.push(((converted >> 8) & 0xFF) as u8);
@@ -195,7 +254,83 @@ pub fn format_query(format: String, args: Vec<QueryValue>) -> Result<Vec<u8>, Qu
 }
 
 /// Scans a result using a standard input to recieve from the player
+///
+/// Any bytes left over after the end of the format are an error, see
+/// [`scan_query_lenient`] to ignore them instead.
 pub fn scan_query(query_result: Vec<u8>, format: String) -> Result<Vec<QueryValue>, QueryError> {
+    scan_query_inner(query_result, format, false)
+}
+
+/// Scans a result like [`scan_query`], but ignores any bytes left over after
+/// the end of the format, which some devices append to their replies
+pub fn scan_query_lenient(
+    query_result: Vec<u8>,
+    format: String,
+) -> Result<Vec<QueryValue>, QueryError> {
+    scan_query_inner(query_result, format, true)
+}
+
+/// The first optional group of a format, split out of it
+struct OptionalGroup<'a> {
+    prefix: &'a str,
+    group: &'a str,
+    suffix: &'a str,
+}
+
+/// Find the first optional group in a format
+fn find_optional_group(format: &str) -> Result<Option<OptionalGroup<'_>>, QueryError> {
+    let Some(start) = format.find("%(") else {
+        return Ok(None);
+    };
+    let Some(length) = format[start..].find(")?") else {
+        return Err(QueryError::UnclosedGroup(format.to_string()));
+    };
+    let end = start + length;
+
+    Ok(Some(OptionalGroup {
+        prefix: &format[..start],
+        group: &format[start + 2..end],
+        suffix: &format[end + 2..],
+    }))
+}
+
+/// Take the next `S` bytes of the input
+fn take_bytes<const S: usize>(input: &mut std::vec::IntoIter<u8>) -> Result<[u8; S], QueryError> {
+    let bytes: Vec<u8> = input.take(S).collect();
+    bytes.try_into().or(Err(QueryError::UnexpectedEnd))
+}
+
+fn scan_query_inner(
+    query_result: Vec<u8>,
+    format: String,
+    lenient: bool,
+) -> Result<Vec<QueryValue>, QueryError> {
+    // Try the reply with each optional group present first. It is only
+    // scanned without the group if the reply doesn't match the group itself,
+    // so errors before or after the group are still returned.
+    if let Some(OptionalGroup {
+        prefix,
+        group,
+        suffix,
+    }) = find_optional_group(&format)?
+    {
+        let with_group = [prefix, group, suffix].concat();
+        let error = match scan_query_inner(query_result.clone(), with_group, lenient) {
+            Ok(result) => return Ok(result),
+            Err(error) => error,
+        };
+
+        let prefix_matches =
+            scan_query_inner(query_result.clone(), prefix.to_string(), true).is_ok();
+        let group_matches =
+            scan_query_inner(query_result.clone(), [prefix, group].concat(), true).is_ok();
+        if !prefix_matches || group_matches {
+            return Err(error);
+        }
+
+        return scan_query_inner(query_result, [prefix, suffix].concat(), lenient);
+    }
+
     let mut result: Vec<QueryValue> = Vec::new();
 
     let initial_length = query_result.len();
@@ -223,39 +358,43 @@ pub fn scan_query(query_result: Vec<u8>, format: String) -> Result<Vec<QueryValu
 
             match character {
                 character if FORMAT_TYPE_LEN_DICT.contains_key(&character) => {
-                    match character {
-                        'b' => {
-                            let new_value =
-                                u8::from_be_bytes(utils::get_bytes(&mut input_stack).unwrap());
-                            result.push(QueryValue::Number(new_value as i64));
-                        }
+                    let little_endian = endianness_override == Some('<');
+                    let new_value = match character {
+                        'b' => u8::from_be_bytes(take_bytes(&mut input_stack)?) as i64,
                         'w' => {
-                            let new_value =
-                                i16::from_be_bytes(utils::get_bytes(&mut input_stack).unwrap());
-                            result.push(QueryValue::Number(new_value as i64));
+                            let bytes = take_bytes(&mut input_stack)?;
+                            match little_endian {
+                                true => i16::from_le_bytes(bytes) as i64,
+                                false => i16::from_be_bytes(bytes) as i64,
+                            }
                         }
                         'd' => {
-                            let new_value =
-                                i32::from_be_bytes(utils::get_bytes(&mut input_stack).unwrap());
-                            result.push(QueryValue::Number(new_value as i64));
+                            let bytes = take_bytes(&mut input_stack)?;
+                            match little_endian {
+                                true => i32::from_le_bytes(bytes) as i64,
+                                false => i32::from_be_bytes(bytes) as i64,
+                            }
                         }
                         'q' => {
-                            let new_value =
-                                i64::from_be_bytes(utils::get_bytes(&mut input_stack).unwrap());
-                            result.push(QueryValue::Number(new_value));
+                            let bytes = take_bytes(&mut input_stack)?;
+                            match little_endian {
+                                true => i64::from_le_bytes(bytes),
+                                false => i64::from_be_bytes(bytes),
+                            }
                         }
                         _ => unreachable!(),
                     };
+                    result.push(QueryValue::Number(new_value));
                     endianness_override = None;
                 }
                 character if character == 'x' || character == 's' || character == 'z' => {
                     let length = match character {
-                        'z' => input_stack.next().unwrap() as u16,
-                        _ => u16::from_be_bytes(utils::get_bytes(&mut input_stack).unwrap()),
+                        'z' => input_stack.next().ok_or(QueryError::UnexpectedEnd)? as u16,
+                        _ => u16::from_be_bytes(take_bytes(&mut input_stack)?),
                     };
                     let mut result_buffer: Vec<u8> = Vec::new();
                     for _ in 0..length {
-                        result_buffer.push(input_stack.next().unwrap());
+                        result_buffer.push(input_stack.next().ok_or(QueryError::UnexpectedEnd)?);
                     }
                     result.push(QueryValue::Array(result_buffer))
                 }
@@ -269,12 +408,12 @@ pub fn scan_query(query_result: Vec<u8>, format: String) -> Result<Vec<QueryValu
                     result.push(QueryValue::Array(result_buffer));
                 }
                 'B' => {
-                    let v = input_stack.next().unwrap();
+                    let v = input_stack.next().ok_or(QueryError::UnexpectedEnd)?;
                     result.push(QueryValue::Number(utils::bcd_to_int(v as i32) as i64));
                 }
                 'W' => {
-                    let v = (input_stack.next().unwrap() as i32) << 8
-                        | input_stack.next().unwrap() as i32;
+                    let v = (input_stack.next().ok_or(QueryError::UnexpectedEnd)? as i32) << 8
+                        | input_stack.next().ok_or(QueryError::UnexpectedEnd)? as i32;
                     result.push(QueryValue::Number(utils::bcd_to_int(v) as i64));
                 }
                 _ => return Err(QueryError::UnrecognizedChar(character)),
@@ -292,7 +431,7 @@ pub fn scan_query(query_result: Vec<u8>, format: String) -> Result<Vec<QueryValu
         if half.is_none() {
             half = Some(character);
         } else {
-            let input_value = input_stack.next().unwrap();
+            let input_value = input_stack.next().ok_or(QueryError::UnexpectedEnd)?;
             let format_value =
                 u8::from_str_radix(&String::from_iter([half.unwrap(), character]), 16).unwrap();
             if format_value != input_value {
@@ -308,6 +447,112 @@ pub fn scan_query(query_result: Vec<u8>, format: String) -> Result<Vec<QueryValu
         }
     }
 
-    assert_eq!(input_stack.len(), 0);
+    if !lenient && input_stack.len() != 0 {
+        return Err(QueryError::TrailingData(input_stack.len()));
+    }
+
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Format `value` with `format`, check the bytes and scan them back
+    fn round_trip(format: &str, value: i64, bytes: &[u8]) {
+        let formatted = format_query(format.to_string(), vec![QueryValue::Number(value)]).unwrap();
+        assert_eq!(formatted, bytes, "formatting {format}");
+
+        // Replies start with a status byte, which is skipped when scanning
+        let reply = [&[0x09], bytes].concat();
+        let scanned = scan_query(reply, format.to_string()).unwrap();
+        assert_eq!(scanned[0].to_i64().unwrap(), value, "scanning {format}");
+    }
+
+    #[test]
+    fn endianness_round_trip() {
+        for format in ["%b", "%<b", "%>b"] {
+            round_trip(format, 0x12, &[0x12]);
+        }
+
+        round_trip("%w", 0x1234, &[0x12, 0x34]);
+        round_trip("%>w", 0x1234, &[0x12, 0x34]);
+        round_trip("%<w", 0x1234, &[0x34, 0x12]);
+
+        round_trip("%d", 0x12345678, &[0x12, 0x34, 0x56, 0x78]);
+        round_trip("%>d", 0x12345678, &[0x12, 0x34, 0x56, 0x78]);
+        round_trip("%<d", 0x12345678, &[0x78, 0x56, 0x34, 0x12]);
+
+        let q = 0x0123456789abcdef;
+        let be = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+        let mut le = be;
+        le.reverse();
+        round_trip("%q", q, &be);
+        round_trip("%>q", q, &be);
+        round_trip("%<q", q, &le);
+    }
+
+    #[test]
+    fn endianness_override_applies_once() {
+        let format = "%<w %w".to_string();
+        let args = vec![QueryValue::Number(0x1234), QueryValue::Number(0x1234)];
+        let formatted = format_query(format.clone(), args).unwrap();
+        assert_eq!(formatted, [0x34, 0x12, 0x12, 0x34]);
+
+        let scanned = scan_query([&[0x09], &formatted[..]].concat(), format).unwrap();
+        assert_eq!(scanned[0].to_i64().unwrap(), 0x1234);
+        assert_eq!(scanned[1].to_i64().unwrap(), 0x1234);
+    }
+
+    #[test]
+    fn optional_group_present_and_absent() {
+        let format = "1800 %(01)? %b";
+
+        let present = scan_query(vec![0x09, 0x18, 0x00, 0x01, 0x05], format.to_string()).unwrap();
+        assert_eq!(present.len(), 1);
+        assert_eq!(present[0].to_i64().unwrap(), 5);
+
+        let absent = scan_query(vec![0x09, 0x18, 0x00, 0x05], format.to_string()).unwrap();
+        assert_eq!(absent.len(), 1);
+        assert_eq!(absent[0].to_i64().unwrap(), 5);
+    }
+
+    #[test]
+    fn optional_group_keeps_errors_outside_the_group() {
+        // The group matches, so the mismatch after it is reported rather
+        // than the one from scanning without the group
+        let result = scan_query(
+            vec![0x09, 0x18, 0x00, 0x01, 0x03],
+            "1800 %(01)? 02".to_string(),
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            QueryError::InputMismatch {
+                index: 4,
+                expected: 0x02,
+                actual: 0x03,
+                format_string: "1800 01 02".to_string(),
+            }
+        );
+
+        // A mismatch before the group
+        let result = scan_query(
+            vec![0x09, 0x18, 0x01, 0x01, 0x02],
+            "1800 %(01)? 02".to_string(),
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            QueryError::InputMismatch {
+                index: 2,
+                expected: 0x00,
+                actual: 0x01,
+                format_string: "1800 01 02".to_string(),
+            }
+        );
+
+        assert_eq!(
+            scan_query(vec![0x09, 0x18], "18 %(00".to_string()).unwrap_err(),
+            QueryError::UnclosedGroup("18 %(00".to_string())
+        );
+    }
+}
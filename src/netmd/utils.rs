@@ -3,7 +3,7 @@ use byteorder::{LittleEndian, WriteBytesExt};
 use diacritics;
 use encoding_rs::SHIFT_JIS;
 use regex::Regex;
-use std::{error::Error, io::Write, time::Duration, vec::IntoIter};
+use std::{io::Write, time::Duration};
 use unicode_normalization::UnicodeNormalization;
 
 extern crate kana;
@@ -15,8 +15,16 @@ use super::{
 };
 
 /// Sleep for a specified [Duration] on any platform
+///
+/// With the `tokio-timer` feature enabled, native platforms sleep using
+/// the tokio timer, which lets other tasks run in the meantime but must be
+/// called from within a tokio runtime. Otherwise the sleep blocks the
+/// calling thread, which stops every other task on that thread from running.
 pub async fn cross_sleep(duration: Duration) {
-    #[cfg(not(target_family = "wasm"))]
+    #[cfg(all(not(target_family = "wasm"), feature = "tokio-timer"))]
+    tokio::time::sleep(duration).await;
+
+    #[cfg(all(not(target_family = "wasm"), not(feature = "tokio-timer")))]
     std::thread::sleep(duration);
 
     #[cfg(target_family = "wasm")]
@@ -51,26 +59,40 @@ pub fn int_to_bcd(mut value: i32) -> i32 {
     bcd
 }
 
+/// Calculate the checksum sent with data written through the factory
+/// interface.
+///
+/// This is a CRC-16 with the polynomial 0x1021, kept in the upper half of
+/// a 32 bit register. The length of the data is loaded into the lower half
+/// first, then each byte is shifted through it.
+#[cfg(feature = "factory-writes")]
+pub fn calculate_checksum(data: &[u8]) -> u16 {
+    let mut crc: u32 = data.len() as u32 & 0xffff;
+
+    for &byte in data {
+        crc = (crc & 0xffffff00) | byte as u32;
+
+        for _ in 0..8 {
+            let carry = crc & 0x80000000 != 0;
+            crc <<= 1;
+            if carry {
+                crc ^= 0x10210000;
+            }
+        }
+    }
+
+    (crc >> 16) as u16
+}
+
+/// Convert a group range to full width, leaving characters which can't
+/// be part of a valid range as they are
 pub fn half_width_to_full_width_range(range: &str) -> String {
     range
         .chars()
-        .map(|char| HW_TO_FW_RANGE_MAP.get(&char).unwrap())
+        .map(|char| *HW_TO_FW_RANGE_MAP.get(&char).unwrap_or(&char))
         .collect()
 }
 
-pub fn get_bytes<const S: usize>(iterator: &mut IntoIter<u8>) -> Result<[u8; S], Box<dyn Error>> {
-    let byte_vec: Vec<u8> = iterator.take(S).collect();
-    let bytes: [u8; S] = byte_vec.try_into().unwrap();
-
-    Ok(bytes)
-}
-
-pub fn length_after_encoding_to_sjis(string: &str) -> usize {
-    let new_string = SHIFT_JIS.encode(string);
-
-    new_string.0.len()
-}
-
 pub fn validate_sjis(sjis_string: Vec<u8>) -> bool {
     let (_, _, had_errors) = SHIFT_JIS.decode(&sjis_string);
 
@@ -89,14 +111,18 @@ fn check(string: String) -> Option<String> {
     None
 }
 
+/// The number of bytes a half width title takes up in the TOC.
+///
+/// This starts from the number of characters in the title, then adds the
+/// extra bytes listed in [`MULTI_BYTE_CHARS`] for characters which are
+/// stored as more than one half width character.
 pub fn half_width_title_length(title: &str) -> usize {
-    let multibyte_len = title
+    let multibyte_len: usize = title
         .chars()
-        .map(|c| (*MULTI_BYTE_CHARS.get(&c).unwrap_or(&0) as usize))
-        .reduce(|a, b| a + b)
-        .unwrap_or_default();
+        .map(|c| *MULTI_BYTE_CHARS.get(&c).unwrap_or(&0) as usize)
+        .sum();
 
-    title.len() + multibyte_len
+    title.chars().count() + multibyte_len
 }
 
 pub fn sanitize_half_width_title(title: &str) -> String {
@@ -108,8 +134,16 @@ pub fn sanitize_half_width_title(title: &str) -> String {
     let new_title: String = string_title
         .chars()
         .map(|c| {
-            check(c.to_string()).unwrap_or(
-                check(diacritics::remove_diacritics(&c.to_string())).unwrap_or(" ".to_string()),
+            let character = c.to_string();
+            if let Some(romanized) = MAPPINGS_DE
+                .get(character.as_str())
+                .or(MAPPINGS_RU.get(character.as_str()))
+            {
+                return romanized.to_string();
+            }
+
+            check(character.clone()).unwrap_or(
+                check(diacritics::remove_diacritics(&character)).unwrap_or(" ".to_string()),
             )
         })
         .collect();
@@ -170,6 +204,12 @@ pub fn agressive_sanitize_title(title: &str) -> String {
     .into()
 }
 
+/// The number of sound groups in `length` bytes of SP audio. A sound group
+/// is 512 samples, which is 212 bytes for each channel.
+pub fn aea_sound_groups(length: usize, channels: u32) -> u32 {
+    (length / (212 * channels.max(1) as usize)) as u32
+}
+
 pub struct AeaOptions<'a> {
     pub name: &'a str,
     pub channels: u32,
@@ -240,6 +280,9 @@ pub fn create_aea_header(options: AeaOptions) -> Vec<u8> {
         .write_u32::<LittleEndian>(options.group_start)
         .unwrap();
 
+    // The audio starts after the 2048 bytes given at the start
+    header.resize(2048, 0);
+
     // return the header
     header
 }
@@ -299,17 +342,225 @@ impl From<RawTime> for Duration {
     }
 }
 
+impl std::ops::Add for RawTime {
+    type Output = RawTime;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        RawTime::from_frames(self.as_frames() + rhs.as_frames())
+    }
+}
+
+impl std::iter::Sum for RawTime {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        RawTime::from_frames(iter.map(|t| t.as_frames()).sum())
+    }
+}
+
 impl RawTime {
     pub fn as_duration(&self) -> Duration {
-        std::time::Duration::from_micros(
-            (self.hours * 3600000000)
-                + (self.minutes * 60000000)
-                + (self.seconds * 1000000)
-                + (self.frames * 11600),
-        )
+        // A frame is 1/512th of a second, which is exactly 1953125ns
+        std::time::Duration::from_nanos(self.as_frames() * 1_000_000_000 / 512)
     }
 
     pub fn as_frames(&self) -> u64 {
         ((self.hours * 60 + self.minutes) * 60 + self.seconds) * 512 + self.frames
     }
+
+    /// Create a [`RawTime`] from a number of frames, the inverse of [`RawTime::as_frames`]
+    pub fn from_frames(frames: u64) -> Self {
+        let seconds = frames / 512;
+
+        Self {
+            hours: seconds / 3600,
+            minutes: (seconds / 60) % 60,
+            seconds: seconds % 60,
+            frames: frames % 512,
+        }
+    }
+
+    /// Format the time as `HH:MM:SS`, optionally followed by the frames
+    /// (1/512ths of a second) as `HH:MM:SS+FFF`
+    pub fn to_hms_string(self, frames: bool) -> String {
+        let hms = format!("{:02}:{:02}:{:02}", self.hours, self.minutes, self.seconds);
+
+        if frames {
+            format!("{}+{:03}", hms, self.frames)
+        } else {
+            hms
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_width_title_length_ascii() {
+        assert_eq!(half_width_title_length(""), 0);
+        assert_eq!(half_width_title_length("Hello"), 5);
+        assert_eq!(half_width_title_length("0;Disc//1-2;A//"), 15);
+    }
+
+    #[test]
+    fn half_width_title_length_katakana() {
+        // Each character becomes a single half width katakana
+        assert_eq!(half_width_title_length("カタカナ"), 4);
+        assert_eq!(half_width_title_length("ｶﾀｶﾅ"), 4);
+    }
+
+    #[test]
+    fn half_width_title_length_multi_byte() {
+        // Voiced kana gain a separate dakuten or handakuten mark
+        assert_eq!(half_width_title_length("ガ"), 2);
+        assert_eq!(half_width_title_length("パピプペポ"), 10);
+        assert_eq!(half_width_title_length("ぱ"), 2);
+
+        for (c, extra) in MULTI_BYTE_CHARS.entries() {
+            assert_eq!(half_width_title_length(&c.to_string()), 1 + *extra as usize);
+        }
+    }
+
+    #[test]
+    fn multi_byte_chars_match_half_width_mappings() {
+        // A character takes as many extra bytes as its half width form
+        // has Shift-JIS bytes beyond the first
+        for (full, half) in MAPPINGS_HW.entries() {
+            let mut chars = full.chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                continue;
+            };
+
+            let extra = SHIFT_JIS.encode(half).0.len() - 1;
+            assert_eq!(
+                *MULTI_BYTE_CHARS.get(&c).unwrap_or(&0) as usize,
+                extra,
+                "{} => {}",
+                full,
+                half
+            );
+        }
+
+        // Every listed character is one which can be written
+        for c in MULTI_BYTE_CHARS.keys() {
+            let c = c.to_string();
+            assert!(MAPPINGS_HW.contains_key(&c) || ALLOWED_HW_KANA.contains(&c.as_str()));
+        }
+    }
+
+    #[test]
+    fn range_mappings_reverse() {
+        for c in "0123456789-/;".chars() {
+            let full_width = HW_TO_FW_RANGE_MAP
+                .get(&c)
+                .unwrap_or_else(|| panic!("{} has no full width form", c));
+
+            assert_eq!(
+                MAPPINGS_HW.get(full_width.to_string().as_str()),
+                Some(&c.to_string().as_str())
+            );
+        }
+
+        assert_eq!(half_width_to_full_width_range("1-12"), "１－１２");
+    }
+
+    #[test]
+    fn sanitize_half_width() {
+        for (title, expected) in [
+            ("Hello, World!", "Hello, World!"),
+            ("café", "cafe"),
+            ("Crème Brûlée", "Creme Brulee"),
+            ("naïve", "naive"),
+            ("Über Grüße", "Ueber Gruesse"),
+            ("Привет мир", "Privet mir"),
+            ("Щука", "Shchuka"),
+            ("ＡＢＣ　１２", "ABC 12"),
+            ("カタカナ", "ｶﾀｶﾅ"),
+            ("ガ", "ｶﾞ"),
+            ("がぎぐ", "ｶﾞｷﾞｸﾞ"),
+            ("パン", "ﾊﾟﾝ"),
+            ("ｶﾞ", "ｶﾞ"),
+        ] {
+            assert_eq!(sanitize_half_width_title(title), expected, "{}", title);
+        }
+    }
+
+    #[test]
+    fn sanitize_full_width() {
+        for (title, expected) in [
+            ("(Live)", "（Ｌｉｖｅ）"),
+            ("Hello, World!", "Ｈｅｌｌｏ，　Ｗｏｒｌｄ！"),
+            ("Щука", "Shchuka"),
+            ("ガ", "ガ"),
+            ("がぎぐ", "がぎぐ"),
+            ("カタカナ", "カタカナ"),
+        ] {
+            assert_eq!(sanitize_full_width_title(title), expected, "{}", title);
+        }
+    }
+
+    #[test]
+    fn half_width_title_length_mixed() {
+        assert_eq!(half_width_title_length("Track ガイド 1"), 13);
+        assert_eq!(half_width_title_length("ABCカ"), 4);
+    }
+
+    #[test]
+    fn hms_string() {
+        let time = |seconds: u64, frames: u64| RawTime::from_frames(seconds * 512 + frames);
+
+        assert_eq!(time(0, 0).to_hms_string(false), "00:00:00");
+        assert_eq!(time(0, 0).to_hms_string(true), "00:00:00+000");
+        assert_eq!(time(59, 511).to_hms_string(false), "00:00:59");
+        assert_eq!(time(59, 511).to_hms_string(true), "00:00:59+511");
+        assert_eq!(time(60, 0).to_hms_string(true), "00:01:00+000");
+        assert_eq!(time(3600 + 62, 7).to_hms_string(false), "01:01:02");
+        assert_eq!(time(3600 + 62, 7).to_hms_string(true), "01:01:02+007");
+    }
+
+    #[test]
+    fn raw_time_as_duration() {
+        assert_eq!(RawTime::from_frames(0).as_duration(), Duration::ZERO);
+        assert_eq!(
+            RawTime::from_frames(1).as_duration(),
+            Duration::from_nanos(1_953_125)
+        );
+        assert_eq!(
+            RawTime::from_frames(256).as_duration(),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            RawTime::from_frames(512).as_duration(),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            RawTime::from_frames(512 * 3661 + 511).as_duration(),
+            Duration::from_secs(3662) - Duration::from_nanos(1_953_125)
+        );
+        assert_eq!(
+            Duration::from(RawTime::from_frames(512 * 60 * 80)),
+            Duration::from_secs(80 * 60)
+        );
+    }
+
+    #[test]
+    fn aea_sound_groups_per_channel() {
+        assert_eq!(aea_sound_groups(212 * 10, 1), 10);
+        assert_eq!(aea_sound_groups(424 * 10, 2), 10);
+        assert_eq!(aea_sound_groups(424 * 10 + 423, 2), 10);
+        assert_eq!(aea_sound_groups(211, 1), 0);
+
+        for channels in [1, 2] {
+            let header = create_aea_header(AeaOptions {
+                name: "Track",
+                channels,
+                sound_groups: aea_sound_groups(424 * 10, channels),
+                ..Default::default()
+            });
+
+            assert_eq!(header.len(), 2048);
+            assert_eq!(&header[260..264], (20 / channels).to_le_bytes());
+            assert_eq!(header[264], channels as u8);
+        }
+    }
 }